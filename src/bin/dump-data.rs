@@ -1,9 +1,88 @@
-use mediumrare::client::{Client, PostDataClient};
+use mediumrare::client::{self, Client, ClientError, PostDataClient};
+
+#[derive(Debug, thiserror::Error)]
+enum DumpError {
+    #[error("usage: dump-data <post id or medium url>")]
+    MissingArgument,
+
+    #[error("no medium post id in {0:?}")]
+    NoPostId(String),
+
+    #[error("{0}")]
+    Client(#[from] ClientError),
+}
+
+impl DumpError {
+    fn exit_code(&self) -> i32 {
+        match self {
+            DumpError::MissingArgument | DumpError::NoPostId(_) => 2,
+            DumpError::Client(ClientError::NotFoundError(_)) => 3,
+            DumpError::Client(_) => 1,
+        }
+    }
+}
+
+/// accepts a bare post id or a full medium url, like the web handlers do
+fn post_id_from_arg(arg: &str) -> Result<String, DumpError> {
+    client::extract_post_id(arg).ok_or_else(|| DumpError::NoPostId(arg.to_string()))
+}
+
+fn dump<C: PostDataClient>(arg: Option<String>, client: &C) -> Result<String, DumpError> {
+    let post_id = post_id_from_arg(&arg.ok_or(DumpError::MissingArgument)?)?;
+
+    Ok(client.get_post_data_raw(&post_id)?)
+}
 
 fn main() {
-    let input = std::env::args().nth(1).unwrap();
-    let c = Client;
-    let data = c.get_post_data(&input).unwrap();
+    match dump(std::env::args().nth(1), &Client::default()) {
+        Ok(data) => println!("{}", data),
+        Err(err) => {
+            eprintln!("{}", err);
+            std::process::exit(err.exit_code());
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{dump, post_id_from_arg};
+    use mediumrare::client::{ClientError, PostDataClient, QueryResponse};
+
+    struct NotFoundClient;
+
+    impl PostDataClient for NotFoundClient {
+        fn get_post_data(&self, post_id: &str) -> Result<QueryResponse, ClientError> {
+            Err(ClientError::NotFoundError(post_id.to_string()))
+        }
+    }
+
+    #[test]
+    fn test_urls_and_bare_ids_are_accepted() {
+        assert_eq!(
+            "a1b2c3d4e5f6",
+            post_id_from_arg("https://medium.com/@jdoe/a-sample-post-a1b2c3d4e5f6?source=rss")
+                .unwrap()
+        );
+        assert_eq!("a1b2c3d4e5f6", post_id_from_arg("a1b2c3d4e5f6").unwrap());
+    }
+
+    #[test]
+    fn test_args_without_id_are_rejected() {
+        assert!(post_id_from_arg("https://medium.com/@jdoe").is_err());
+        assert!(post_id_from_arg("").is_err());
+    }
+
+    #[test]
+    fn test_missing_argument_exit_code() {
+        let err = dump(None, &NotFoundClient).unwrap_err();
+        assert_eq!(2, err.exit_code());
+        assert!(err.to_string().starts_with("usage: "));
+    }
 
-    println!("{}", serde_json::to_string(&data).unwrap());
+    #[test]
+    fn test_not_found_exit_code() {
+        let err = dump(Some(String::from("a1b2c3d4e5f6")), &NotFoundClient).unwrap_err();
+        assert_eq!(3, err.exit_code());
+        assert_eq!("not found: a1b2c3d4e5f6", err.to_string());
+    }
 }
@@ -0,0 +1,54 @@
+use mediumrare::client::Client;
+use mediumrare::feed::atom_feed;
+
+const DEFAULT_LIMIT: u32 = 10;
+
+fn main() {
+    let mut positional = Vec::new();
+    let mut limit = DEFAULT_LIMIT;
+
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--limit" => {
+                let count = args.next().expect("--limit needs a post count");
+                limit = count.parse().expect("invalid post count");
+            }
+            _ => positional.push(arg),
+        }
+    }
+
+    let [username, base_url] = positional.as_slice() else {
+        eprintln!("usage: feed <username> <mediumrare base url> [--limit <count>]");
+        std::process::exit(2);
+    };
+
+    let client = Client::default();
+    let post_ids = match client.get_user_post_ids(username, limit) {
+        Ok(post_ids) => post_ids,
+        Err(err) => {
+            eprintln!("{}", err);
+            std::process::exit(1);
+        }
+    };
+
+    let post_ids: Vec<&str> = post_ids.iter().map(String::as_str).collect();
+    let entries = match client.get_posts(&post_ids) {
+        Ok(entries) => entries,
+        Err(err) => {
+            eprintln!("{}", err);
+            std::process::exit(1);
+        }
+    };
+
+    // a post that failed to load is left out of the feed rather than failing it
+    let mut posts = Vec::with_capacity(entries.len());
+    for (post_id, result) in entries {
+        match result {
+            Ok(post) => posts.push(post),
+            Err(err) => eprintln!("skipping {}: {}", post_id, err),
+        }
+    }
+
+    print!("{}", atom_feed(username, &posts, base_url));
+}
@@ -1,10 +1,306 @@
-use mediumrare::client::QueryResponse;
-use mediumrare::content::Render;
+use anyhow::{anyhow, Result};
+use mediumrare::client::{self, Client, PostDataClient, PostResult, QueryResponse};
+use mediumrare::content::{Render, RenderOptions};
+use mediumrare::html::Page;
+use mediumrare::markdown;
+use std::path::{Path, PathBuf};
+
+/// where the post comes from, a json dump or a live fetch of a medium url
+enum Input {
+    File(String),
+    Url(String),
+}
+
+fn load_post<C: PostDataClient>(input: &Input, client: &C) -> Result<PostResult> {
+    let data: QueryResponse = match input {
+        Input::File(path) => serde_json::from_slice(&std::fs::read(path)?)?,
+        Input::Url(url) => {
+            let post_id =
+                client::extract_post_id(url).ok_or_else(|| anyhow!("no post id in {}", url))?;
+            client.get_post_data(&post_id)?
+        }
+    };
+
+    Ok(data.get_post())
+}
+
+/// writes to `path`, creating missing parent directories, or to stdout without one
+fn write_output(path: Option<&Path>, output: &str) -> std::io::Result<()> {
+    let Some(path) = path else {
+        print!("{}", output);
+        return Ok(());
+    };
+
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    std::fs::write(path, output)
+}
+
+enum Format {
+    Html,
+    /// the full document as served, head included
+    Page,
+    Markdown,
+    MarkdownFrontMatter,
+}
+
+impl Format {
+    /// of the files written in batch mode
+    fn extension(&self) -> &'static str {
+        match self {
+            Format::Html | Format::Page => "html",
+            Format::Markdown | Format::MarkdownFrontMatter => "md",
+        }
+    }
+}
+
+fn render(
+    post: &PostResult,
+    format: &Format,
+    options: &RenderOptions,
+    pretty: bool,
+) -> Result<String> {
+    let content = post.render_with(options)?;
+
+    Ok(match format {
+        Format::Html if pretty => content.to_pretty_string(2),
+        Format::Html => format!("{}\n", content),
+        Format::Page => format!("{}\n", Page::new(post).render_with(options)?),
+        Format::Markdown => markdown::to_markdown(&content),
+        Format::MarkdownFrontMatter => markdown::to_markdown_with_front_matter(post, &content),
+    })
+}
+
+/// renders every `*.json` dump in `dir` to a sibling file of the same name, a dump
+/// that fails doesn't stop the others. returns each dump with the file written for it
+fn render_dir<C: PostDataClient>(
+    dir: &Path,
+    client: &C,
+    format: &Format,
+    options: &RenderOptions,
+    pretty: bool,
+) -> std::io::Result<Vec<(PathBuf, Result<PathBuf>)>> {
+    let mut dumps = std::fs::read_dir(dir)?
+        .map(|entry| entry.map(|entry| entry.path()))
+        .collect::<std::io::Result<Vec<PathBuf>>>()?;
+    dumps.retain(|path| path.is_file() && path.extension().is_some_and(|ext| ext == "json"));
+    dumps.sort();
+
+    Ok(dumps
+        .into_iter()
+        .map(|dump| {
+            let target = dump.with_extension(format.extension());
+            let input = Input::File(dump.to_string_lossy().into_owned());
+            let result = load_post(&input, client)
+                .and_then(|post| render(&post, format, options, pretty))
+                .and_then(|rendered| write_output(Some(&target), &rendered).map_err(Into::into))
+                .map(|_| target);
+            (dump, result)
+        })
+        .collect())
+}
 
 fn main() {
-    let input = std::env::args().nth(1).unwrap();
-    let file = std::fs::read(input).unwrap();
-    let data: QueryResponse = serde_json::from_slice(&file).unwrap();
+    let mut input = None;
+    let mut options = RenderOptions::default();
+    let mut format = Format::Html;
+    let mut list_types = false;
+    let mut pretty = false;
+    let mut assert_type = None;
+    let mut output = None;
+
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--preview" => {
+                let limit = args.next().expect("--preview needs a paragraph count");
+                options.preview_limit = Some(limit.parse().expect("invalid paragraph count"));
+            }
+            "--number-headings" => options.number_headings = true,
+            "--image-width" => {
+                let width = args.next().expect("--image-width needs a pixel width");
+                options.image_width = Some(width.parse().expect("invalid pixel width"));
+            }
+            "--format" => {
+                format = match args.next().as_deref() {
+                    Some("html") => Format::Html,
+                    Some("page") => Format::Page,
+                    Some("markdown") => Format::Markdown,
+                    Some("markdown-frontmatter") => Format::MarkdownFrontMatter,
+                    other => panic!("unknown format {:?}", other),
+                }
+            }
+            "--list-types" => list_types = true,
+            "--pretty" => pretty = true,
+            "--assert-type" => {
+                assert_type = Some(args.next().expect("--assert-type needs a paragraph type"))
+            }
+            "--url" => input = Some(Input::Url(args.next().expect("--url needs a medium url"))),
+            "--output" => {
+                output = Some(PathBuf::from(
+                    args.next().expect("--output needs a file path"),
+                ))
+            }
+            _ if input.is_none() => input = Some(Input::File(arg)),
+            _ => output = Some(PathBuf::from(arg)),
+        }
+    }
+
+    let input = input.expect("missing input file or --url");
+    let client = Client::default();
+    if let Input::File(path) = &input {
+        if Path::new(path).is_dir() {
+            let results = match render_dir(Path::new(path), &client, &format, &options, pretty) {
+                Ok(results) => results,
+                Err(err) => {
+                    eprintln!("{}", err);
+                    std::process::exit(1);
+                }
+            };
+            let mut failed = 0;
+            for (dump, result) in &results {
+                match result {
+                    Ok(target) => println!("{} -> {}", dump.display(), target.display()),
+                    Err(err) => {
+                        failed += 1;
+                        eprintln!("{}: {}", dump.display(), err);
+                    }
+                }
+            }
+            println!(
+                "rendered {} of {} dumps",
+                results.len() - failed,
+                results.len()
+            );
+            std::process::exit(if failed > 0 { 1 } else { 0 });
+        }
+    }
+
+    let post = match load_post(&input, &client) {
+        Ok(post) => post,
+        Err(err) => {
+            eprintln!("{}", err);
+            std::process::exit(1);
+        }
+    };
+
+    if list_types || assert_type.is_some() {
+        let types = post.paragraph_types();
+        if list_types {
+            for ((r#type, layout), count) in &types {
+                println!(
+                    "{}\t{}\t{}",
+                    r#type,
+                    layout.as_deref().unwrap_or("-"),
+                    count
+                );
+            }
+        }
+        if let Some(unwanted) = assert_type {
+            if types.keys().any(|(r#type, _)| *r#type == unwanted) {
+                eprintln!("found paragraphs of type {}", unwanted);
+                std::process::exit(1);
+            }
+        }
+        return;
+    }
+
+    let rendered = render(&post, &format, &options, pretty).unwrap();
+    if let Err(err) = write_output(output.as_deref(), &rendered) {
+        eprintln!("{}", err);
+        std::process::exit(1);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{load_post, render, render_dir, write_output, Format, Input};
+    use mediumrare::client::{ClientError, PostDataClient, QueryResponse};
+    use mediumrare::content::RenderOptions;
+    use std::cell::RefCell;
+
+    #[derive(Default)]
+    struct RecordingClient {
+        requested: RefCell<Vec<String>>,
+    }
+
+    impl PostDataClient for RecordingClient {
+        fn get_post_data(&self, post_id: &str) -> Result<QueryResponse, ClientError> {
+            self.requested.borrow_mut().push(post_id.to_string());
+            Ok(serde_json::from_str(include_str!("../../test/post.json"))?)
+        }
+    }
+
+    #[test]
+    fn test_url_input_fetches_extracted_id() {
+        let client = RecordingClient::default();
+        let input = Input::Url(String::from(
+            "https://medium.com/@jdoe/a-sample-post-a1b2c3d4e5f6?source=rss",
+        ));
+
+        let post = load_post(&input, &client).unwrap();
+        assert_eq!("A Sample Post", post.title);
+        assert_eq!(
+            vec![String::from("a1b2c3d4e5f6")],
+            *client.requested.borrow()
+        );
+    }
+
+    #[test]
+    fn test_output_file_gets_what_stdout_would() {
+        let dir = std::env::temp_dir().join(format!("render-dump-output-{}", std::process::id()));
+        let path = dir.join("nested").join("post.html");
+        let post = serde_json::from_str::<QueryResponse>(include_str!("../../test/post.json"))
+            .unwrap()
+            .get_post();
+        // stdout mode prints exactly this
+        let rendered = render(&post, &Format::Html, &RenderOptions::default(), false).unwrap();
+
+        write_output(Some(&path), &rendered).unwrap();
+        let written = std::fs::read_to_string(&path);
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(rendered, written.unwrap());
+        assert!(rendered.starts_with("<article>"));
+    }
+
+    #[test]
+    fn test_batch_renders_valid_dumps_despite_broken_ones() {
+        let dir = std::env::temp_dir().join(format!("render-dump-batch-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("good.json"), include_str!("../../test/post.json")).unwrap();
+        std::fs::write(dir.join("broken.json"), "{\"data\":").unwrap();
+        std::fs::write(dir.join("notes.txt"), "not a dump").unwrap();
+
+        let client = RecordingClient::default();
+        let results = render_dir(
+            &dir,
+            &client,
+            &Format::Html,
+            &RenderOptions::default(),
+            false,
+        )
+        .unwrap();
+        let good_html = std::fs::read_to_string(dir.join("good.html"));
+        let broken_html_exists = dir.join("broken.html").exists();
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(2, results.len());
+        assert!(results[0].0.ends_with("broken.json"));
+        assert!(results[0].1.is_err());
+        assert!(results[1].0.ends_with("good.json"));
+        assert!(results[1].1.as_ref().unwrap().ends_with("good.html"));
+        assert!(good_html.unwrap().contains("A Sample Post"));
+        assert!(!broken_html_exists);
+        assert!(client.requested.borrow().is_empty());
+    }
+
+    #[test]
+    fn test_url_without_post_id_is_rejected() {
+        let client = RecordingClient::default();
+        let input = Input::Url(String::from("https://medium.com/@jdoe"));
 
-    println!("{}", data.get_post().render().unwrap().to_string());
+        assert!(load_post(&input, &client).is_err());
+        assert!(client.requested.borrow().is_empty());
+    }
 }
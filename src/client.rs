@@ -1,9 +1,16 @@
+use crate::timestamp::DateTime;
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::future::Future;
+use std::sync::{Arc, Condvar, Mutex, PoisonError};
+use std::thread;
+use std::time::{Duration, Instant};
+use ureq::ResponseExt;
 
-const QUERY_TEXT: &str = "query PostHandler($postId:ID!) {
-    postResult(id: $postId) { 
-        ... on Post { 
+/// the fields fetched for every post, shared between the single and the batch query
+macro_rules! post_fields {
+    () => {
+        "
             title, 
             id, 
             mediumUrl, 
@@ -49,6 +56,9 @@ const QUERY_TEXT: &str = "query PostHandler($postId:ID!) {
                                 title,
                             }
                         },
+                        codeBlockMetadata {
+                            lang
+                        },
                         metadata { 
                             id, 
                             originalHeight, 
@@ -59,14 +69,25 @@ const QUERY_TEXT: &str = "query PostHandler($postId:ID!) {
                             start, 
                             end, 
                             type, 
-                            href 
+                            href,
+                            userId
                         } 
                     }
                 }
-            } 
-        } 
+            }
+        "
+    };
+}
+
+const QUERY_TEXT: &str = concat!(
+    "query PostHandler($postId:ID!) {
+    postResult(id: $postId) { 
+        ... on Post { ",
+    post_fields!(),
+    "} 
     } 
-}";
+}"
+);
 
 #[derive(Debug, Serialize)]
 #[serde(rename_all = "camelCase")]
@@ -79,31 +100,100 @@ pub struct QueryRequest<'a> {
 #[derive(Debug, Deserialize, Serialize, Clone)]
 #[serde(rename_all = "camelCase")]
 pub struct PostResult {
-    id: String,
+    pub(crate) id: String,
     pub medium_url: String,
     pub title: String,
+    // medium leaves out some metadata, e.g. for unpublished drafts. a partial post
+    // still has a title and a body worth rendering
+    #[serde(default)]
     clap_count: u32,
-    created_at: usize,
-    updated_at: usize,
-    latest_published_at: usize,
+    #[serde(default)]
+    created_at: i64,
+    #[serde(default)]
+    updated_at: i64,
+    #[serde(default)]
+    latest_published_at: i64,
+    #[serde(default)]
     reading_time: f32,
-    preview_image: PreviewImage,
+    #[serde(default)]
+    pub(crate) preview_image: PreviewImage,
     pub creator: Creator,
+    #[serde(default)]
     pub tags: Vec<Tag>,
-    topics: Vec<Topic>,
-    content: Content,
+    #[serde(default)]
+    pub topics: Vec<Topic>,
+    #[serde(default)]
+    content: Option<Content>,
+    /// fields we don't model, kept so re-serializing doesn't lose them
+    #[serde(flatten)]
+    pub extra: HashMap<String, serde_json::Value>,
 }
 
 impl PostResult {
-    pub fn paragraphs(&self) -> &Vec<Paragraph> {
-        &self.content.body_model.paragraphs
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+
+    /// milliseconds since the epoch, like all of medium's timestamps
+    pub fn updated_at(&self) -> i64 {
+        self.updated_at
+    }
+
+    pub fn latest_published_at(&self) -> i64 {
+        self.latest_published_at
+    }
+
+    pub fn created_time(&self) -> DateTime {
+        DateTime::from_epoch_ms(self.created_at)
+    }
+
+    pub fn updated_time(&self) -> DateTime {
+        DateTime::from_epoch_ms(self.updated_at)
+    }
+
+    pub fn published_time(&self) -> DateTime {
+        DateTime::from_epoch_ms(self.latest_published_at)
+    }
+
+    /// the body paragraphs, empty when medium didn't send a body at all
+    pub fn paragraphs(&self) -> &[Paragraph] {
+        self.content
+            .as_ref()
+            .and_then(|content| content.body_model.as_ref())
+            .map_or(&[], |body| &body.paragraphs)
+    }
+
+    /// a short description for link previews: the first body paragraph, or the
+    /// author's bio for posts without one
+    pub fn summary(&self) -> &str {
+        self.paragraphs()
+            .iter()
+            .filter(|paragraph| paragraph.r#type == "P")
+            .find_map(|paragraph| paragraph.text.as_deref().filter(|text| !text.is_empty()))
+            .unwrap_or(&self.creator.bio)
+    }
+
+    pub fn preview_image_id(&self) -> Option<&str> {
+        Some(self.preview_image.id.as_str()).filter(|id| !id.is_empty())
+    }
+
+    /// how often each paragraph `(type, layout)` combination occurs in the body
+    pub fn paragraph_types(&self) -> BTreeMap<(String, Option<String>), usize> {
+        let mut counts = BTreeMap::new();
+        for paragraph in self.paragraphs() {
+            *counts
+                .entry((paragraph.r#type.clone(), paragraph.layout.clone()))
+                .or_insert(0) += 1;
+        }
+        counts
     }
 }
 
 #[derive(Debug, Deserialize, Serialize, Clone)]
 #[serde(rename_all = "camelCase")]
 pub struct Content {
-    body_model: BodyModel,
+    #[serde(default)]
+    body_model: Option<BodyModel>,
 }
 #[derive(Debug, Deserialize, Serialize, Clone)]
 #[serde(rename_all = "camelCase")]
@@ -122,6 +212,11 @@ pub struct Paragraph {
     pub markups: Vec<Markup>,
     pub metadata: Option<Metadata>,
     pub iframe: Option<IFrame>,
+    #[serde(default)]
+    pub code_block_metadata: Option<CodeBlockMetadata>,
+    /// fields we don't model, kept so re-serializing doesn't lose them
+    #[serde(flatten)]
+    pub extra: HashMap<String, serde_json::Value>,
 }
 
 #[derive(Debug, Deserialize, Serialize, Clone)]
@@ -140,13 +235,23 @@ pub struct IFrameMediaResource {
     pub title: String,
 }
 
+/// set on `PRE` paragraphs
+#[derive(Debug, Deserialize, Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct CodeBlockMetadata {
+    /// the language the author picked for highlighting, if any
+    pub lang: Option<String>,
+}
+
 #[derive(Debug, Deserialize, Serialize, Clone)]
 #[serde(rename_all = "camelCase")]
 pub struct Metadata {
-    alt: Option<String>,
+    pub(crate) alt: Option<String>,
     pub id: String,
-    original_width: usize,
-    original_height: usize,
+    #[serde(default)]
+    pub(crate) original_width: Option<usize>,
+    #[serde(default)]
+    pub(crate) original_height: Option<usize>,
 }
 
 #[derive(Debug, Deserialize, Serialize, Clone)]
@@ -156,21 +261,24 @@ pub struct Markup {
     pub start: usize,
     pub href: Option<String>,
     pub r#type: String,
+    /// set on `USER` mentions
+    #[serde(default)]
+    pub user_id: Option<String>,
 }
 
 #[derive(Debug, Deserialize, Serialize, Clone)]
 #[serde(rename_all = "camelCase")]
 pub struct Topic {
     topic_id: String,
-    name: String,
+    pub name: String,
 }
 
 #[derive(Debug, Deserialize, Serialize, Clone)]
 #[serde(rename_all = "camelCase")]
 pub struct Tag {
     id: String,
-    display_title: String,
-    normalized_tag_slug: String,
+    pub display_title: String,
+    pub normalized_tag_slug: String,
 }
 
 #[derive(Debug, Deserialize, Serialize, Clone)]
@@ -182,12 +290,12 @@ pub struct Creator {
     pub bio: String,
 }
 
-#[derive(Debug, Deserialize, Serialize, Clone)]
+#[derive(Debug, Deserialize, Serialize, Clone, Default)]
 #[serde(rename_all = "camelCase")]
 pub struct PreviewImage {
-    id: String,
-    original_width: Option<usize>,
-    original_height: Option<usize>,
+    pub(crate) id: String,
+    pub(crate) original_width: Option<usize>,
+    pub(crate) original_height: Option<usize>,
 }
 
 #[derive(Debug, Deserialize, Serialize, Clone)]
@@ -208,23 +316,392 @@ impl QueryResponse {
     }
 }
 
-fn create_post_query(post_id: &str) -> QueryRequest {
+const UPDATED_AT_QUERY_TEXT: &str = "query PostUpdatedAt($postId:ID!) {
+    postResult(id: $postId) {
+        ... on Post {
+            updatedAt
+        }
+    }
+}";
+
+/// medium post ids are the hex suffix of the post slug
+fn is_post_id(candidate: &str) -> bool {
+    (10..=12).contains(&candidate.len()) && candidate.chars().all(|c| c.is_ascii_hexdigit())
+}
+
+/// accepts a bare post id or a full medium url, like
+/// `https://medium.com/@author/some-title-a1b2c3d4e5f6` or `https://medium.com/p/a1b2c3d4e5f6`,
+/// and returns the post id
+pub fn extract_post_id(input: &str) -> Option<String> {
+    let path = input.split(['?', '#']).next()?.trim_end_matches('/');
+    let segment = path.rsplit('/').next()?;
+    let candidate = segment.rsplit('-').next()?;
+    is_post_id(candidate).then(|| candidate.to_ascii_lowercase())
+}
+
+/// the `href` of the `<link rel="canonical">` in a medium page
+fn canonical_url(html: &str) -> Option<&str> {
+    html.split("<link ")
+        .skip(1)
+        .map(|tag| &tag[..tag.find('>').unwrap_or(tag.len())])
+        .find(|tag| tag.contains(r#"rel="canonical""#))
+        .and_then(|tag| tag.split(r#"href=""#).nth(1))
+        .and_then(|href| href.split('"').next())
+}
+
+fn create_post_query(post_id: &str) -> QueryRequest<'_> {
+    create_query("PostHandler", QUERY_TEXT, post_id)
+}
+
+fn create_query<'a>(operation_name: &'a str, query: &'a str, post_id: &'a str) -> QueryRequest<'a> {
     let mut hash_map = HashMap::new();
     hash_map.insert("postId", post_id);
     QueryRequest {
-        operation_name: "PostHandler",
-        query: QUERY_TEXT,
+        operation_name,
+        query,
         variables: hash_map,
     }
 }
 
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct BatchQueryRequest<'a> {
+    operation_name: &'a str,
+    query: String,
+    variables: HashMap<String, &'a str>,
+}
+
+/// fetches all posts in one request by aliasing `postResult` once per id
+fn create_batch_query<'a>(post_ids: &[&'a str]) -> BatchQueryRequest<'a> {
+    let aliases = (0..post_ids.len()).map(|idx| format!("p{}", idx));
+    let arguments: Vec<String> = aliases
+        .clone()
+        .map(|alias| format!("${}:ID!", alias))
+        .collect();
+    let selections: Vec<String> = aliases
+        .clone()
+        .map(|alias| format!("{alias}: postResult(id: ${alias}) {{ ...PostFields }}"))
+        .collect();
+
+    BatchQueryRequest {
+        operation_name: "PostsHandler",
+        query: format!(
+            "query PostsHandler({}) {{ {} }} fragment PostFields on Post {{ {} }}",
+            arguments.join(", "),
+            selections.join(" "),
+            post_fields!()
+        ),
+        variables: aliases.zip(post_ids.iter().copied()).collect(),
+    }
+}
+
+/// the requested post id together with the outcome for that single post
+pub type BatchEntry = (String, Result<PostResult, ClientError>);
+
+#[derive(Debug, Deserialize)]
+struct BatchQueryResponse {
+    data: HashMap<String, serde_json::Value>,
+}
+
+fn parse_batch_response(
+    post_ids: &[&str],
+    response_text: &str,
+) -> Result<Vec<BatchEntry>, ClientError> {
+    let mut data = serde_json::from_str::<BatchQueryResponse>(response_text)?.data;
+
+    Ok(post_ids
+        .iter()
+        .enumerate()
+        .map(|(idx, post_id)| {
+            let result = match data.remove(&format!("p{}", idx)) {
+                Some(serde_json::Value::Object(post)) if !post.is_empty() => {
+                    serde_json::from_value(serde_json::Value::Object(post)).map_err(Into::into)
+                }
+                _ => Err(ClientError::NotFoundError(post_id.to_string())),
+            };
+            (post_id.to_string(), result)
+        })
+        .collect())
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct UpdatedAtResponse {
+    data: UpdatedAtData,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct UpdatedAtData {
+    post_result: UpdatedAtPost,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct UpdatedAtPost {
+    updated_at: i64,
+}
+
+const USER_POSTS_QUERY_TEXT: &str = "query UserPosts($username:ID!, $limit:Int) {
+    userResult(username: $username) {
+        __typename
+        ... on User {
+            homepagePostsConnection(paging: {limit: $limit}) {
+                posts {
+                    id
+                }
+            }
+        }
+    }
+}";
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct UserPostsResponse {
+    data: UserPostsData,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct UserPostsData {
+    user_result: UserPostsUser,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct UserPostsUser {
+    /// `User`, or `NotFound` for unknown usernames
+    #[serde(rename = "__typename")]
+    typename: String,
+    homepage_posts_connection: Option<UserPostsConnection>,
+}
+
+#[derive(Debug, Deserialize)]
+struct UserPostsConnection {
+    posts: Vec<UserPost>,
+}
+
+#[derive(Debug, Deserialize)]
+struct UserPost {
+    id: String,
+}
+
+fn parse_user_posts_response(
+    username: &str,
+    response_text: &str,
+) -> Result<Vec<String>, ClientError> {
+    let user = serde_json::from_str::<UserPostsResponse>(response_text)?
+        .data
+        .user_result;
+    match user.homepage_posts_connection {
+        Some(connection) if user.typename == "User" => {
+            Ok(connection.posts.into_iter().map(|post| post.id).collect())
+        }
+        _ => Err(ClientError::NotFoundError(username.to_string())),
+    }
+}
+
 pub trait PostDataClient {
     fn get_post_data(&self, post_id: &str) -> Result<QueryResponse, ClientError>;
+
+    /// the response as json text. clients talking to medium return it unparsed, so
+    /// fields we don't model survive, the default re-serializes `get_post_data`
+    fn get_post_data_raw(&self, post_id: &str) -> Result<String, ClientError> {
+        Ok(serde_json::to_string(&self.get_post_data(post_id)?)?)
+    }
+
+    /// fetches the post only if its `updated_at` differs from `token`, returns `None`
+    /// when the post is unchanged
+    fn get_post_data_if_changed(
+        &self,
+        post_id: &str,
+        token: i64,
+    ) -> Result<Option<QueryResponse>, ClientError> {
+        let response = self.get_post_data(post_id)?;
+        if response.data.post_result.updated_at == token {
+            return Ok(None);
+        }
+
+        Ok(Some(response))
+    }
+}
+
+/// the non-blocking counterpart of `PostDataClient`, for handlers running on tokio
+pub trait AsyncPostDataClient {
+    fn get_post_data(
+        &self,
+        post_id: &str,
+    ) -> impl Future<Output = Result<QueryResponse, ClientError>> + Send;
+}
+
+/// runs a blocking client on tokio's blocking thread pool, so slow fetches don't
+/// stall the runtime's workers
+pub struct SpawnBlockingClient<C> {
+    inner: Arc<C>,
+}
+
+impl<C> SpawnBlockingClient<C> {
+    pub fn new(inner: C) -> SpawnBlockingClient<C> {
+        SpawnBlockingClient {
+            inner: Arc::new(inner),
+        }
+    }
+
+    pub fn inner(&self) -> &C {
+        &self.inner
+    }
+}
+
+impl<C: PostDataClient + Send + Sync + 'static> AsyncPostDataClient for SpawnBlockingClient<C> {
+    fn get_post_data(
+        &self,
+        post_id: &str,
+    ) -> impl Future<Output = Result<QueryResponse, ClientError>> + Send {
+        let inner = self.inner.clone();
+        let post_id = post_id.to_string();
+        async move {
+            tokio::task::spawn_blocking(move || inner.get_post_data(&post_id))
+                .await
+                .expect("blocking fetch panicked")
+        }
+    }
+}
+
+const GRAPHQL_ENDPOINT: &str = "https://medium.com/_/graphql";
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(10);
+const DEFAULT_RETRY_BACKOFF: Duration = Duration::from_millis(200);
+const DEFAULT_USER_AGENT: &str = concat!("mediumrare/", env!("CARGO_PKG_VERSION"));
+/// assumed when a 429 comes without a `Retry-After` in seconds
+const DEFAULT_RETRY_AFTER: Duration = Duration::from_secs(60);
+/// longer `Retry-After`s are returned as `RateLimited` instead of waited out
+const MAX_RETRY_AFTER_WAIT: Duration = Duration::from_secs(5);
+/// the exponential backoff between retries never grows beyond this
+const MAX_RETRY_BACKOFF: Duration = Duration::from_secs(5);
+
+/// `backoff` doubled for every attempt so far, capped at [`MAX_RETRY_BACKOFF`]
+fn retry_delay(backoff: Duration, attempt: u32) -> Duration {
+    backoff
+        .saturating_mul(2u32.saturating_pow(attempt))
+        .min(MAX_RETRY_BACKOFF)
+}
+
+pub struct Client {
+    agent: ureq::Agent,
+    endpoint: String,
+    timeout: Duration,
+    user_agent: String,
+    max_retries: u32,
+    /// the delay before the first retry, doubled for every further one
+    retry_backoff: Duration,
+    /// medium's `sid` session cookie, unlocks member-only posts
+    auth_token: Option<String>,
+}
+
+impl Default for Client {
+    fn default() -> Self {
+        ClientBuilder::default().build()
+    }
+}
+
+/// collects the configuration of a `Client`, options left unset keep the defaults
+/// of `Client::default()`, not `Debug` as that would print the auth token
+#[derive(Clone)]
+pub struct ClientBuilder {
+    endpoint: String,
+    timeout: Duration,
+    user_agent: String,
+    max_retries: u32,
+    retry_backoff: Duration,
+    auth_token: Option<String>,
+}
+
+impl Default for ClientBuilder {
+    fn default() -> Self {
+        ClientBuilder {
+            endpoint: GRAPHQL_ENDPOINT.to_string(),
+            timeout: DEFAULT_TIMEOUT,
+            user_agent: DEFAULT_USER_AGENT.to_string(),
+            max_retries: 0,
+            retry_backoff: DEFAULT_RETRY_BACKOFF,
+            auth_token: None,
+        }
+    }
 }
 
-pub struct Client;
+impl ClientBuilder {
+    /// the graphql url queries are sent to, medium's own by default
+    pub fn endpoint<S: Into<String>>(mut self, endpoint: S) -> ClientBuilder {
+        self.endpoint = endpoint.into();
+        self
+    }
+
+    /// see `Client::with_timeout`
+    pub fn timeout(mut self, timeout: Duration) -> ClientBuilder {
+        self.timeout = timeout;
+        self
+    }
+
+    /// see `Client::with_user_agent`
+    pub fn user_agent<S: Into<String>>(mut self, user_agent: S) -> ClientBuilder {
+        self.user_agent = user_agent.into();
+        self
+    }
+
+    /// see `Client::with_retries`
+    pub fn retries(mut self, max: u32) -> ClientBuilder {
+        self.max_retries = max;
+        self
+    }
+
+    /// the delay before the first retry, doubled for every further one
+    pub fn retry_backoff(mut self, backoff: Duration) -> ClientBuilder {
+        self.retry_backoff = backoff;
+        self
+    }
+
+    /// see `Client::with_auth_token`
+    pub fn auth_token<S: Into<String>>(mut self, token: S) -> ClientBuilder {
+        self.auth_token = Some(token.into());
+        self
+    }
+
+    pub fn build(self) -> Client {
+        Client {
+            agent: build_agent(self.timeout),
+            endpoint: self.endpoint,
+            timeout: self.timeout,
+            user_agent: self.user_agent,
+            max_retries: self.max_retries,
+            retry_backoff: self.retry_backoff,
+            auth_token: self.auth_token,
+        }
+    }
+}
+
+/// `Retry-After` may also be an http date, which medium doesn't send
+fn parse_retry_after(value: &str) -> Option<Duration> {
+    value.trim().parse().ok().map(Duration::from_secs)
+}
+
+fn build_agent(timeout: Duration) -> ureq::Agent {
+    ureq::Agent::config_builder()
+        .timeout_connect(Some(timeout))
+        .timeout_recv_response(Some(timeout))
+        .timeout_recv_body(Some(timeout))
+        // error responses carry graphql messages in their body, the status is checked
+        // after reading it
+        .http_status_as_error(false)
+        .build()
+        .into()
+}
+
+pub struct Image {
+    pub content_type: String,
+    pub data: Vec<u8>,
+}
 
 #[derive(Debug, thiserror::Error)]
+#[allow(clippy::enum_variant_names)]
 pub enum ClientError {
     #[error("not found: {0}")]
     NotFoundError(String),
@@ -234,24 +711,1438 @@ pub enum ClientError {
 
     #[error("failed decoding json")]
     EncodingError(#[from] serde_json::Error),
+
+    #[error("medium reported errors: {}", .0.join("; "))]
+    GraphQlError(Vec<String>),
+
+    #[error("rate limited, retry in {retry_after:?}")]
+    RateLimited { retry_after: Duration },
+}
+
+#[derive(Debug, Deserialize)]
+struct GraphQlErrorMessage {
+    message: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct GraphQlErrors {
+    #[serde(default)]
+    errors: Vec<GraphQlErrorMessage>,
+}
+
+/// graphql reports failures in a top level `errors` array, often next to a 200 status
+fn check_graphql_errors(response_text: &str) -> Result<(), ClientError> {
+    let Ok(GraphQlErrors { errors }) = serde_json::from_str(response_text) else {
+        return Ok(());
+    };
+    if errors.is_empty() {
+        return Ok(());
+    }
+
+    Err(ClientError::GraphQlError(
+        errors.into_iter().map(|error| error.message).collect(),
+    ))
 }
 
 impl PostDataClient for Client {
     fn get_post_data(&self, post_id: &str) -> Result<QueryResponse, ClientError> {
-        let mut response = ureq::post("https://medium.com/_/graphql")
-            .header("Content-Type", "application/json")
-            .send_json(create_post_query(post_id))?;
+        let response_text = self.get_post_data_raw(post_id)?;
+
+        Ok(serde_json::from_str::<QueryResponse>(&response_text)?)
+    }
+
+    fn get_post_data_raw(&self, post_id: &str) -> Result<String, ClientError> {
+        self.query(post_id, create_post_query(post_id))
+    }
+
+    /// asks medium for just the `updatedAt` of the post first, which is a lot cheaper
+    /// than fetching the whole body when it hasn't changed anyway
+    fn get_post_data_if_changed(
+        &self,
+        post_id: &str,
+        token: i64,
+    ) -> Result<Option<QueryResponse>, ClientError> {
+        let response_text = self.query(
+            post_id,
+            create_query("PostUpdatedAt", UPDATED_AT_QUERY_TEXT, post_id),
+        )?;
+        let updated_at = serde_json::from_str::<UpdatedAtResponse>(&response_text)?
+            .data
+            .post_result
+            .updated_at;
+        if updated_at == token {
+            return Ok(None);
+        }
+
+        self.get_post_data(post_id).map(Some)
+    }
+}
+
+impl Client {
+    pub fn new() -> Client {
+        Client::default()
+    }
+
+    pub fn builder() -> ClientBuilder {
+        ClientBuilder::default()
+    }
+
+    /// sends queries to `endpoint` instead of medium, e.g. a mirror or a test server
+    pub fn with_endpoint<S: Into<String>>(mut self, endpoint: S) -> Client {
+        self.endpoint = endpoint.into();
+        self
+    }
+
+    /// limits how long connecting to and reading from medium may take
+    pub fn with_timeout(mut self, timeout: Duration) -> Client {
+        self.timeout = timeout;
+        self.agent = build_agent(self.timeout);
+        self
+    }
+
+    /// medium blocks some clients by their user agent, this replaces `mediumrare/<version>`
+    pub fn with_user_agent(mut self, user_agent: String) -> Client {
+        self.user_agent = user_agent;
+        self
+    }
+
+    /// retries queries failing with a 5xx status or a connection error up to `max`
+    /// times, waiting exponentially longer between attempts
+    pub fn with_retries(mut self, max: u32) -> Client {
+        self.max_retries = max;
+        self
+    }
 
+    /// sends the `sid` session cookie of a medium account with every query, member-only
+    /// posts are truncated without it
+    pub fn with_auth_token<S: Into<String>>(mut self, token: S) -> Client {
+        self.auth_token = Some(token.into());
+        self
+    }
+
+    /// finds the post id for a medium url that doesn't carry one, like a bare
+    /// `medium.com/@user/slug`, by following its redirects and looking at the final
+    /// url and the page's canonical link
+    pub fn resolve_post_id(&self, url: &str) -> Result<String, ClientError> {
+        if let Some(post_id) = extract_post_id(url) {
+            return Ok(post_id);
+        }
+
+        let mut response = self
+            .agent
+            .get(url)
+            .header("User-Agent", &self.user_agent)
+            .call()?;
+        if let Some(post_id) = extract_post_id(&response.get_uri().to_string()) {
+            return Ok(post_id);
+        }
         if response.status() == 404 {
+            return Err(ClientError::NotFoundError(url.to_string()));
+        }
+
+        let html = response.body_mut().read_to_string()?;
+        canonical_url(&html)
+            .and_then(extract_post_id)
+            .ok_or_else(|| ClientError::NotFoundError(url.to_string()))
+    }
+
+    /// the ids of the `limit` most recent posts of the medium user `username`, newest first
+    pub fn get_user_post_ids(
+        &self,
+        username: &str,
+        limit: u32,
+    ) -> Result<Vec<String>, ClientError> {
+        let username = username.trim_start_matches('@');
+        let query = serde_json::json!({
+            "operationName": "UserPosts",
+            "query": USER_POSTS_QUERY_TEXT,
+            "variables": { "username": username, "limit": limit },
+        });
+        let response_text = self.query(username, query)?;
+        parse_user_posts_response(username, &response_text)
+    }
+
+    /// fetches several posts with a single graphql request, the result contains one
+    /// entry per requested id in the same order
+    pub fn get_posts(&self, post_ids: &[&str]) -> Result<Vec<BatchEntry>, ClientError> {
+        if post_ids.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let response_text = self.query(&post_ids.join(","), create_batch_query(post_ids))?;
+        parse_batch_response(post_ids, &response_text)
+    }
+
+    fn query<Q: Serialize>(&self, post_id: &str, query: Q) -> Result<String, ClientError> {
+        let body = serde_json::to_string(&query)?;
+        let mut attempt = 0;
+        let (status, response_text) = loop {
+            match self.send_query(&body) {
+                Ok((status, _)) if status >= 500 && attempt < self.max_retries => {}
+                Err(ClientError::RequestError(
+                    ureq::Error::Io(_) | ureq::Error::ConnectionFailed | ureq::Error::Timeout(_),
+                )) if attempt < self.max_retries => {}
+                Err(ClientError::RateLimited { retry_after })
+                    if retry_after <= MAX_RETRY_AFTER_WAIT && attempt < self.max_retries =>
+                {
+                    thread::sleep(retry_after);
+                    attempt += 1;
+                    continue;
+                }
+                result => break result?,
+            }
+
+            thread::sleep(retry_delay(self.retry_backoff, attempt));
+            attempt += 1;
+        };
+
+        if status == 404 {
             return Err(ClientError::NotFoundError(post_id.to_string()));
         }
 
-        let response_text = response.body_mut().read_to_string().unwrap();
+        check_graphql_errors(&response_text)?;
+        if !(200..300).contains(&status) {
+            return Err(ureq::Error::StatusCode(status).into());
+        }
 
         if response_text == "{\"data\":{\"postResult\":{}}}\n" {
             return Err(ClientError::NotFoundError(post_id.to_string()));
         }
 
-        Ok(serde_json::from_str::<QueryResponse>(&response_text)?)
+        Ok(response_text)
+    }
+
+    fn send_query(&self, body: &str) -> Result<(u16, String), ClientError> {
+        let mut request = self
+            .agent
+            .post(&self.endpoint)
+            .header("Content-Type", "application/json")
+            .header("User-Agent", &self.user_agent);
+        if let Some(token) = &self.auth_token {
+            request = request.header("Cookie", format!("sid={}", token));
+        }
+        let mut response = request.send(body)?;
+        if response.status() == 429 {
+            let retry_after = response
+                .headers()
+                .get("Retry-After")
+                .and_then(|value| value.to_str().ok())
+                .and_then(parse_retry_after)
+                .unwrap_or(DEFAULT_RETRY_AFTER);
+            return Err(ClientError::RateLimited { retry_after });
+        }
+
+        Ok((
+            response.status().as_u16(),
+            response.body_mut().read_to_string()?,
+        ))
+    }
+
+    /// fetches the image from medium's cdn, resized to fit `width`
+    pub fn get_image(&self, image_id: &str, width: u32) -> Result<Image, ClientError> {
+        let mut response = self
+            .agent
+            .get(format!(
+                "https://miro.medium.com/v2/resize:fit:{}/{}",
+                width, image_id
+            ))
+            .header("User-Agent", &self.user_agent)
+            .call()?;
+        if !response.status().is_success() {
+            return Err(ureq::Error::StatusCode(response.status().as_u16()).into());
+        }
+
+        let content_type = response
+            .headers()
+            .get("Content-Type")
+            .and_then(|v| v.to_str().ok())
+            .unwrap_or("application/octet-stream")
+            .to_string();
+
+        Ok(Image {
+            content_type,
+            data: response.body_mut().read_to_vec()?,
+        })
+    }
+}
+
+/// the outcome of an upstream fetch as seen by the requests that waited for it,
+/// errors can't be cloned so waiters only learn whether the post was missing
+#[derive(Clone)]
+enum SharedResult {
+    Found(Box<QueryResponse>),
+    NotFound,
+    Failed,
+}
+
+#[derive(Default)]
+struct Flight {
+    result: Mutex<Option<SharedResult>>,
+    done: Condvar,
+}
+
+impl Flight {
+    fn wait(&self) -> SharedResult {
+        let mut result = self.result.lock().unwrap();
+        while result.is_none() {
+            result = self.done.wait(result).unwrap();
+        }
+        result.clone().expect("checked above")
+    }
+
+    fn finish(&self, result: SharedResult) {
+        *self.result.lock().unwrap_or_else(PoisonError::into_inner) = Some(result);
+        self.done.notify_all();
+    }
+}
+
+/// ends the leader's flight when dropped, even when the upstream fetch panicked, so
+/// waiters and later requests for the post don't block forever
+struct LeaderGuard<'a> {
+    in_flight: &'a Mutex<HashMap<String, Arc<Flight>>>,
+    post_id: &'a str,
+    flight: Arc<Flight>,
+    /// what waiters get, stays `Failed` unless the fetch returned
+    result: SharedResult,
+}
+
+impl Drop for LeaderGuard<'_> {
+    fn drop(&mut self) {
+        self.in_flight
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .remove(self.post_id);
+        self.flight
+            .finish(std::mem::replace(&mut self.result, SharedResult::Failed));
+    }
+}
+
+/// coalesces concurrent fetches of the same post, only the first request goes
+/// upstream and everyone waiting for it shares the response
+pub struct SingleFlightClient<C: PostDataClient> {
+    inner: C,
+    in_flight: Mutex<HashMap<String, Arc<Flight>>>,
+}
+
+impl<C: PostDataClient> SingleFlightClient<C> {
+    pub fn new(inner: C) -> SingleFlightClient<C> {
+        SingleFlightClient {
+            inner,
+            in_flight: Mutex::new(HashMap::new()),
+        }
+    }
+}
+
+impl<C: PostDataClient> PostDataClient for SingleFlightClient<C> {
+    fn get_post_data(&self, post_id: &str) -> Result<QueryResponse, ClientError> {
+        let (flight, leader) = {
+            let mut in_flight = self.in_flight.lock().unwrap();
+            match in_flight.get(post_id) {
+                Some(flight) => (flight.clone(), false),
+                None => {
+                    let flight = Arc::new(Flight::default());
+                    in_flight.insert(post_id.to_string(), flight.clone());
+                    (flight, true)
+                }
+            }
+        };
+
+        if !leader {
+            match flight.wait() {
+                SharedResult::Found(response) => return Ok(*response),
+                SharedResult::NotFound => {
+                    return Err(ClientError::NotFoundError(post_id.to_string()))
+                }
+                // the original error went to the leader, try again on our own
+                SharedResult::Failed => return self.inner.get_post_data(post_id),
+            }
+        }
+
+        let mut guard = LeaderGuard {
+            in_flight: &self.in_flight,
+            post_id,
+            flight,
+            result: SharedResult::Failed,
+        };
+        let result = self.inner.get_post_data(post_id);
+        guard.result = match &result {
+            Ok(response) => SharedResult::Found(Box::new(response.clone())),
+            Err(ClientError::NotFoundError(_)) => SharedResult::NotFound,
+            Err(_) => SharedResult::Failed,
+        };
+
+        result
+    }
+
+    fn get_post_data_raw(&self, post_id: &str) -> Result<String, ClientError> {
+        self.inner.get_post_data_raw(post_id)
+    }
+}
+
+struct CacheEntry {
+    post_id: String,
+    response: QueryResponse,
+    fetched_at: Instant,
+}
+
+/// keeps up to `capacity` fetched posts in memory for `ttl`, evicting the least
+/// recently used one when full. lookups scan the entries, which is fine for the few
+/// hundred posts a single instance serves
+pub struct CachingClient<C: PostDataClient> {
+    inner: C,
+    capacity: usize,
+    ttl: Duration,
+    /// least recently used first
+    entries: Mutex<VecDeque<CacheEntry>>,
+}
+
+impl<C: PostDataClient> CachingClient<C> {
+    pub fn new(inner: C, capacity: usize, ttl: Duration) -> CachingClient<C> {
+        CachingClient {
+            inner,
+            capacity,
+            ttl,
+            entries: Mutex::new(VecDeque::with_capacity(capacity)),
+        }
+    }
+
+    fn lookup(&self, post_id: &str) -> Option<QueryResponse> {
+        let mut entries = self.entries.lock().unwrap();
+        let position = entries.iter().position(|entry| entry.post_id == post_id)?;
+        let entry = entries.remove(position)?;
+        if entry.fetched_at.elapsed() >= self.ttl {
+            return None;
+        }
+
+        let response = entry.response.clone();
+        entries.push_back(entry);
+        Some(response)
+    }
+
+    fn store(&self, post_id: &str, response: &QueryResponse) {
+        if self.capacity == 0 {
+            return;
+        }
+
+        let mut entries = self.entries.lock().unwrap();
+        entries.retain(|entry| entry.post_id != post_id);
+        if entries.len() >= self.capacity {
+            entries.pop_front();
+        }
+        entries.push_back(CacheEntry {
+            post_id: post_id.to_string(),
+            response: response.clone(),
+            fetched_at: Instant::now(),
+        });
+    }
+}
+
+impl<C: PostDataClient> PostDataClient for CachingClient<C> {
+    fn get_post_data(&self, post_id: &str) -> Result<QueryResponse, ClientError> {
+        if let Some(response) = self.lookup(post_id) {
+            return Ok(response);
+        }
+
+        let response = self.inner.get_post_data(post_id)?;
+        self.store(post_id, &response);
+        Ok(response)
+    }
+
+    /// always goes upstream, the cache only holds parsed responses
+    fn get_post_data_raw(&self, post_id: &str) -> Result<String, ClientError> {
+        self.inner.get_post_data_raw(post_id)
+    }
+}
+
+/// what `RateLimitedClient` does once the requests of the current second are used up
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RateLimitMode {
+    /// block until the next request is allowed
+    Wait,
+    /// fail with `ClientError::RateLimited`
+    Reject,
+}
+
+struct TokenBucket {
+    tokens: f64,
+    refilled_at: Instant,
+}
+
+/// keeps upstream requests below `per_second` on average, allowing bursts of up to
+/// `per_second` requests after a quiet period. wrap it in a `CachingClient` so cache
+/// hits don't use up requests
+pub struct RateLimitedClient<C: PostDataClient> {
+    inner: C,
+    per_second: f64,
+    mode: RateLimitMode,
+    bucket: Mutex<TokenBucket>,
+}
+
+impl<C: PostDataClient> RateLimitedClient<C> {
+    /// panics if `per_second` is zero, which would never allow a request
+    pub fn new(inner: C, per_second: u32, mode: RateLimitMode) -> RateLimitedClient<C> {
+        assert!(
+            per_second > 0,
+            "a rate limit needs at least one request per second"
+        );
+        RateLimitedClient {
+            inner,
+            per_second: f64::from(per_second),
+            mode,
+            bucket: Mutex::new(TokenBucket {
+                tokens: f64::from(per_second),
+                refilled_at: Instant::now(),
+            }),
+        }
+    }
+
+    /// takes a request from the bucket, or tells how long until the next one is due
+    fn try_acquire(&self) -> Result<(), Duration> {
+        let mut bucket = self.bucket.lock().unwrap();
+        let now = Instant::now();
+        let refill = now.duration_since(bucket.refilled_at).as_secs_f64() * self.per_second;
+        bucket.tokens = (bucket.tokens + refill).min(self.per_second);
+        bucket.refilled_at = now;
+
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            return Ok(());
+        }
+
+        Err(Duration::from_secs_f64(
+            (1.0 - bucket.tokens) / self.per_second,
+        ))
+    }
+
+    fn acquire(&self) -> Result<(), ClientError> {
+        loop {
+            match self.try_acquire() {
+                Ok(()) => return Ok(()),
+                Err(retry_after) if self.mode == RateLimitMode::Wait => thread::sleep(retry_after),
+                Err(retry_after) => return Err(ClientError::RateLimited { retry_after }),
+            }
+        }
+    }
+}
+
+impl<C: PostDataClient> PostDataClient for RateLimitedClient<C> {
+    fn get_post_data(&self, post_id: &str) -> Result<QueryResponse, ClientError> {
+        self.acquire()?;
+        self.inner.get_post_data(post_id)
+    }
+
+    fn get_post_data_raw(&self, post_id: &str) -> Result<String, ClientError> {
+        self.acquire()?;
+        self.inner.get_post_data_raw(post_id)
+    }
+
+    fn get_post_data_if_changed(
+        &self,
+        post_id: &str,
+        token: i64,
+    ) -> Result<Option<QueryResponse>, ClientError> {
+        self.acquire()?;
+        self.inner.get_post_data_if_changed(post_id, token)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::client::{
+        canonical_url, check_graphql_errors, extract_post_id, parse_retry_after,
+        parse_user_posts_response, AsyncPostDataClient, CachingClient, Client, ClientError,
+        Paragraph, PostDataClient, QueryResponse, RateLimitMode, RateLimitedClient,
+        SingleFlightClient, SpawnBlockingClient, GRAPHQL_ENDPOINT,
+    };
+    use crate::content::Render;
+    use crate::timestamp::DateTime;
+    use serde_json::Value;
+    use std::cell::Cell;
+    use std::io::{Read, Write};
+    use std::net::{TcpListener, TcpStream};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::{mpsc, Barrier};
+    use std::thread;
+    use std::time::{Duration, Instant};
+
+    /// answers one connection after the other with the given raw http responses
+    fn stub_responses(responses: Vec<String>) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/_/graphql", listener.local_addr().unwrap());
+        thread::spawn(move || {
+            for response in responses {
+                let (mut stream, _) = listener.accept().unwrap();
+                let mut request = [0; 4096];
+                let _ = stream.read(&mut request).unwrap();
+                stream.write_all(response.as_bytes()).unwrap();
+            }
+        });
+        url
+    }
+
+    fn http_response(status: &str, body: &str) -> String {
+        format!(
+            "HTTP/1.1 {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+            status,
+            body.len(),
+            body
+        )
+    }
+
+    /// serves a single connection on a local port with `handler`, returns the url
+    fn stub_server<F>(handler: F) -> String
+    where
+        F: FnOnce(TcpStream) + Send + 'static,
+    {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/_/graphql", listener.local_addr().unwrap());
+        thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            handler(stream);
+        });
+        url
+    }
+
+    /// answers one connection after the other with the given raw http responses and
+    /// hands each request, headers and body, to the returned receiver
+    fn capturing_stub_responses(responses: Vec<String>) -> (String, mpsc::Receiver<String>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/_/graphql", listener.local_addr().unwrap());
+        let (sender, received) = mpsc::channel();
+        thread::spawn(move || {
+            for response in responses {
+                let (mut stream, _) = listener.accept().unwrap();
+                // the body may arrive in a separate segment after the headers
+                let mut request = String::new();
+                let mut buffer = [0; 4096];
+                while !request.ends_with('}') {
+                    let length = stream.read(&mut buffer).unwrap();
+                    if length == 0 {
+                        break;
+                    }
+                    request.push_str(&String::from_utf8_lossy(&buffer[..length]));
+                }
+                sender.send(request).unwrap();
+                stream.write_all(response.as_bytes()).unwrap();
+            }
+        });
+        (url, received)
+    }
+
+    /// serves a single connection with the sample post and hands the request to the
+    /// returned receiver
+    fn capturing_stub_server() -> (String, mpsc::Receiver<String>) {
+        capturing_stub_responses(vec![http_response(
+            "200 OK",
+            include_str!("../test/post.json"),
+        )])
+    }
+
+    struct CountingClient {
+        calls: Cell<usize>,
+    }
+
+    impl PostDataClient for CountingClient {
+        fn get_post_data(&self, _post_id: &str) -> Result<QueryResponse, ClientError> {
+            self.calls.set(self.calls.get() + 1);
+            Ok(serde_json::from_value(sample_response())?)
+        }
+    }
+
+    fn sample_response() -> Value {
+        serde_json::from_str(include_str!("../test/post.json")).unwrap()
+    }
+
+    #[test]
+    fn test_missing_body_model_renders_header_only() {
+        let mut json = sample_response();
+        json["data"]["postResult"]["content"]
+            .as_object_mut()
+            .unwrap()
+            .remove("bodyModel");
+
+        let post = serde_json::from_value::<QueryResponse>(json)
+            .unwrap()
+            .get_post();
+
+        assert!(post.paragraphs().is_empty());
+        let html = post.render().unwrap().to_string();
+        assert!(html.contains("post-head"));
+        assert!(html.contains("Jane Doe"));
+    }
+
+    #[test]
+    fn test_missing_content_renders_header_only() {
+        let mut json = sample_response();
+        json["data"]["postResult"]
+            .as_object_mut()
+            .unwrap()
+            .remove("content");
+
+        let post = serde_json::from_value::<QueryResponse>(json)
+            .unwrap()
+            .get_post();
+
+        assert!(post.paragraphs().is_empty());
+        assert!(post.render().unwrap().to_string().contains("post-head"));
+    }
+
+    #[test]
+    fn test_if_changed_returns_none_for_unchanged_post() {
+        let client = CountingClient {
+            calls: Cell::new(0),
+        };
+
+        let result = client
+            .get_post_data_if_changed("a1b2c3d4e5f6", 1589608869693)
+            .unwrap();
+        assert!(result.is_none());
+        assert_eq!(1, client.calls.get());
+    }
+
+    #[test]
+    fn test_if_changed_returns_post_for_changed_post() {
+        let client = CountingClient {
+            calls: Cell::new(0),
+        };
+
+        let result = client
+            .get_post_data_if_changed("a1b2c3d4e5f6", 1589522469693)
+            .unwrap();
+        assert_eq!(1589608869693, result.unwrap().get_post().updated_at());
+    }
+
+    #[test]
+    fn test_client_skips_fetch_for_unchanged_post() {
+        let (endpoint, received) = capturing_stub_responses(vec![http_response(
+            "200 OK",
+            r#"{"data":{"postResult":{"updatedAt":1589608869693}}}"#,
+        )]);
+        let client = Client::builder().endpoint(endpoint).build();
+
+        let result = client
+            .get_post_data_if_changed("a1b2c3d4e5f6", 1589608869693)
+            .unwrap();
+        assert!(result.is_none());
+        let requests: Vec<String> = received.try_iter().collect();
+        assert_eq!(1, requests.len());
+        assert!(requests[0].contains("PostUpdatedAt"), "{}", requests[0]);
+    }
+
+    #[test]
+    fn test_client_fetches_changed_post() {
+        let (endpoint, received) = capturing_stub_responses(vec![
+            http_response(
+                "200 OK",
+                r#"{"data":{"postResult":{"updatedAt":1589608869693}}}"#,
+            ),
+            http_response("200 OK", include_str!("../test/post.json")),
+        ]);
+        let client = Client::builder().endpoint(endpoint).build();
+
+        let result = client
+            .get_post_data_if_changed("a1b2c3d4e5f6", 1589522469693)
+            .unwrap();
+        assert_eq!("A Sample Post", result.unwrap().get_post().title);
+        let requests: Vec<String> = received.try_iter().collect();
+        assert_eq!(2, requests.len());
+        assert!(requests[0].contains("PostUpdatedAt"), "{}", requests[0]);
+        assert!(!requests[1].contains("PostUpdatedAt"), "{}", requests[1]);
+    }
+
+    #[test]
+    fn test_timestamps_are_utc_datetimes() {
+        let post = serde_json::from_value::<QueryResponse>(sample_response())
+            .unwrap()
+            .get_post();
+
+        assert_eq!(
+            DateTime {
+                year: 2020,
+                month: 5,
+                day: 16,
+                hour: 6,
+                minute: 1,
+                second: 9,
+            },
+            post.updated_time()
+        );
+        assert_eq!("2020-05-15T06:01:09Z", post.published_time().to_rfc3339());
+        assert_eq!("May 15, 2020", post.created_time().to_human());
+    }
+
+    #[test]
+    fn test_parses_updated_at_response() {
+        let response: super::UpdatedAtResponse =
+            serde_json::from_str(r#"{"data":{"postResult":{"updatedAt":1589608869693}}}"#).unwrap();
+
+        assert_eq!(1589608869693, response.data.post_result.updated_at);
+    }
+
+    #[test]
+    fn test_batch_query_aliases_every_post() {
+        let query = super::create_batch_query(&["aaa", "bbb"]);
+
+        assert!(query
+            .query
+            .starts_with("query PostsHandler($p0:ID!, $p1:ID!) { p0: postResult(id: $p0) { ...PostFields } p1: postResult(id: $p1) { ...PostFields } } fragment PostFields on Post {"));
+        assert_eq!(Some(&"aaa"), query.variables.get("p0"));
+        assert_eq!(Some(&"bbb"), query.variables.get("p1"));
+    }
+
+    #[test]
+    fn test_parses_batch_response_with_missing_post() {
+        let post = sample_response()["data"]["postResult"].clone();
+        let body = serde_json::json!({ "data": { "p0": post, "p1": {} } }).to_string();
+
+        let results = super::parse_batch_response(&["a1b2c3d4e5f6", "missing"], &body).unwrap();
+
+        assert_eq!(2, results.len());
+        assert_eq!("a1b2c3d4e5f6", results[0].0);
+        assert_eq!("A Sample Post", results[0].1.as_ref().unwrap().title);
+        assert_eq!("missing", results[1].0);
+        assert!(matches!(results[1].1, Err(ClientError::NotFoundError(ref id)) if id == "missing"));
+    }
+
+    #[test]
+    fn test_slow_server_times_out() {
+        let endpoint = stub_server(|stream| {
+            thread::sleep(Duration::from_secs(2));
+            drop(stream);
+        });
+        let mut client = Client::new().with_timeout(Duration::from_millis(100));
+        client.endpoint = endpoint;
+
+        let result = client.get_post_data("a1b2c3d4e5f6");
+        assert!(
+            matches!(
+                result,
+                Err(ClientError::RequestError(ureq::Error::Timeout(_)))
+            ),
+            "{:?}",
+            result.err()
+        );
+    }
+
+    #[test]
+    fn test_extract_post_id() {
+        let id = Some(String::from("a1b2c3d4e5f6"));
+        assert_eq!(id, extract_post_id("a1b2c3d4e5f6"));
+        assert_eq!(
+            id,
+            extract_post_id("https://medium.com/@jdoe/a-sample-post-a1b2c3d4e5f6")
+        );
+        assert_eq!(
+            id,
+            extract_post_id("https://medium.com/@jdoe/a-sample-post-a1b2c3d4e5f6?source=rss#top")
+        );
+        assert_eq!(id, extract_post_id("https://medium.com/p/a1b2c3d4e5f6/"));
+        assert_eq!(
+            None,
+            extract_post_id("https://medium.com/@jdoe/a-sample-post")
+        );
+        assert_eq!(None, extract_post_id("favicon.ico"));
+    }
+
+    /// like `CountingClient`, but shareable between threads and slow enough for
+    /// concurrent requests to overlap
+    struct SlowCountingClient {
+        calls: AtomicUsize,
+    }
+
+    impl PostDataClient for SlowCountingClient {
+        fn get_post_data(&self, _post_id: &str) -> Result<QueryResponse, ClientError> {
+            self.calls.fetch_add(1, Ordering::SeqCst);
+            thread::sleep(Duration::from_millis(200));
+            Ok(serde_json::from_value(sample_response())?)
+        }
+    }
+
+    #[test]
+    fn test_single_flight_coalesces_concurrent_fetches() {
+        let client = SingleFlightClient::new(SlowCountingClient {
+            calls: AtomicUsize::new(0),
+        });
+        let barrier = Barrier::new(16);
+
+        thread::scope(|scope| {
+            let handles: Vec<_> = (0..16)
+                .map(|_| {
+                    scope.spawn(|| {
+                        barrier.wait();
+                        client.get_post_data("a1b2c3d4e5f6")
+                    })
+                })
+                .collect();
+            for handle in handles {
+                let post = handle.join().unwrap().unwrap().get_post();
+                assert_eq!("A Sample Post", post.title);
+            }
+        });
+
+        assert_eq!(1, client.inner.calls.load(Ordering::SeqCst));
+        assert!(client.in_flight.lock().unwrap().is_empty());
+    }
+
+    /// panics on its first fetch, after waiting long enough for others to queue up
+    struct PanicOnceClient {
+        calls: AtomicUsize,
+    }
+
+    impl PostDataClient for PanicOnceClient {
+        fn get_post_data(&self, _post_id: &str) -> Result<QueryResponse, ClientError> {
+            if self.calls.fetch_add(1, Ordering::SeqCst) == 0 {
+                thread::sleep(Duration::from_millis(200));
+                panic!("upstream fetch panicked");
+            }
+            Ok(serde_json::from_value(sample_response())?)
+        }
+    }
+
+    #[test]
+    fn test_single_flight_survives_panicking_leader() {
+        let client = SingleFlightClient::new(PanicOnceClient {
+            calls: AtomicUsize::new(0),
+        });
+
+        thread::scope(|scope| {
+            let leader = scope.spawn(|| client.get_post_data("a1b2c3d4e5f6"));
+            thread::sleep(Duration::from_millis(50));
+            let waiter = scope.spawn(|| client.get_post_data("a1b2c3d4e5f6"));
+
+            assert!(leader.join().is_err());
+            let post = waiter.join().unwrap().unwrap().get_post();
+            assert_eq!("A Sample Post", post.title);
+        });
+
+        assert!(client.in_flight.lock().unwrap().is_empty());
+        let post = client.get_post_data("a1b2c3d4e5f6").unwrap().get_post();
+        assert_eq!("A Sample Post", post.title);
+    }
+
+    #[test]
+    fn test_unmodeled_paragraph_fields_survive_round_trip() {
+        let json = serde_json::json!({
+            "id": "p1",
+            "text": "hello",
+            "type": "P",
+            "markups": [],
+            "dropCapImage": { "id": "1*cap.png" }
+        });
+
+        let paragraph = serde_json::from_value::<Paragraph>(json).unwrap();
+        assert_eq!(
+            Some(&serde_json::json!({ "id": "1*cap.png" })),
+            paragraph.extra.get("dropCapImage")
+        );
+
+        let serialized = serde_json::to_value(&paragraph).unwrap();
+        assert_eq!("1*cap.png", serialized["dropCapImage"]["id"]);
+        assert_eq!("hello", serialized["text"]);
+    }
+
+    #[test]
+    fn test_missing_metadata_still_parses() {
+        let mut json = sample_response();
+        let post = json["data"]["postResult"].as_object_mut().unwrap();
+        post.remove("clapCount");
+        post.remove("readingTime");
+        post.remove("previewImage");
+        post.remove("updatedAt");
+
+        let post = serde_json::from_value::<QueryResponse>(json)
+            .unwrap()
+            .get_post();
+        assert_eq!("A Sample Post", post.title);
+        assert_eq!(None, post.preview_image_id());
+        assert_eq!(0, post.updated_at());
+        assert!(!post.paragraphs().is_empty());
+        assert!(post.render().is_ok());
+    }
+
+    #[test]
+    fn test_paragraph_types_counts_type_and_layout() {
+        let mut json = sample_response();
+        let paragraphs = json["data"]["postResult"]["content"]["bodyModel"]["paragraphs"]
+            .as_array_mut()
+            .unwrap();
+        let mut wide_image = paragraphs[2].clone();
+        wide_image["layout"] = Value::from("FULL_WIDTH");
+        paragraphs.push(wide_image);
+        let mut mixtape = paragraphs[3].clone();
+        mixtape["type"] = Value::from("MIXTAPE_EMBED");
+        paragraphs.push(mixtape);
+
+        let post = serde_json::from_value::<QueryResponse>(json)
+            .unwrap()
+            .get_post();
+        let types: Vec<_> = post
+            .paragraph_types()
+            .into_iter()
+            .map(|((r#type, layout), count)| (r#type, layout, count))
+            .collect();
+
+        let layout = |layout: &str| Some(String::from(layout));
+        assert_eq!(
+            vec![
+                (String::from("H3"), None, 1),
+                (String::from("IMG"), layout("FULL_WIDTH"), 1),
+                (String::from("IMG"), layout("INSET_CENTER"), 1),
+                (String::from("MIXTAPE_EMBED"), None, 1),
+                (String::from("P"), None, 2),
+            ],
+            types
+        );
+    }
+
+    fn counting_client() -> CountingClient {
+        CountingClient {
+            calls: Cell::new(0),
+        }
+    }
+
+    #[test]
+    fn test_cache_hit_skips_inner_client() {
+        let client = CachingClient::new(counting_client(), 10, Duration::from_secs(60));
+
+        client.get_post_data("a1b2c3d4e5f6").unwrap();
+        let post = client.get_post_data("a1b2c3d4e5f6").unwrap().get_post();
+
+        assert_eq!("A Sample Post", post.title);
+        assert_eq!(1, client.inner.calls.get());
+    }
+
+    #[test]
+    fn test_rate_limit_rejects_bursts_past_the_limit() {
+        let client = RateLimitedClient::new(counting_client(), 2, RateLimitMode::Reject);
+
+        client.get_post_data("a1b2c3d4e5f6").unwrap();
+        client.get_post_data("a1b2c3d4e5f6").unwrap();
+        let result = client.get_post_data("a1b2c3d4e5f6");
+
+        assert!(
+            matches!(result, Err(ClientError::RateLimited { retry_after })
+                if retry_after > Duration::ZERO && retry_after <= Duration::from_millis(500)),
+            "{:?}",
+            result.err()
+        );
+        assert_eq!(2, client.inner.calls.get());
+    }
+
+    #[test]
+    fn test_rate_limit_waits_for_the_next_request() {
+        let client = RateLimitedClient::new(counting_client(), 20, RateLimitMode::Wait);
+        for _ in 0..20 {
+            client.get_post_data("a1b2c3d4e5f6").unwrap();
+        }
+
+        let started = Instant::now();
+        client.get_post_data("a1b2c3d4e5f6").unwrap();
+
+        assert!(started.elapsed() >= Duration::from_millis(30));
+        assert_eq!(21, client.inner.calls.get());
+    }
+
+    #[test]
+    fn test_cache_refetches_expired_posts() {
+        let client = CachingClient::new(counting_client(), 10, Duration::ZERO);
+
+        client.get_post_data("a1b2c3d4e5f6").unwrap();
+        client.get_post_data("a1b2c3d4e5f6").unwrap();
+
+        assert_eq!(2, client.inner.calls.get());
+    }
+
+    #[test]
+    fn test_cache_evicts_least_recently_used() {
+        let client = CachingClient::new(counting_client(), 2, Duration::from_secs(60));
+
+        client.get_post_data("aaaaaaaaaaaa").unwrap();
+        client.get_post_data("bbbbbbbbbbbb").unwrap();
+        client.get_post_data("aaaaaaaaaaaa").unwrap();
+        client.get_post_data("cccccccccccc").unwrap();
+        assert_eq!(3, client.inner.calls.get());
+
+        // b was used least recently and made room for c
+        client.get_post_data("aaaaaaaaaaaa").unwrap();
+        assert_eq!(3, client.inner.calls.get());
+        client.get_post_data("bbbbbbbbbbbb").unwrap();
+        assert_eq!(4, client.inner.calls.get());
+    }
+
+    #[test]
+    fn test_graphql_errors_are_surfaced() {
+        let body = r#"{"errors":[{"message":"rate limited","path":["postResult"]},{"message":"try again later"}],"data":null}"#;
+
+        let result = check_graphql_errors(body);
+        assert!(
+            matches!(&result, Err(ClientError::GraphQlError(messages)) if messages == &["rate limited", "try again later"]),
+            "{:?}",
+            result
+        );
+        assert!(check_graphql_errors(include_str!("../test/post.json")).is_ok());
+    }
+
+    #[test]
+    fn test_graphql_errors_on_server_error_status() {
+        let endpoint = stub_responses(vec![http_response(
+            "500 Internal Server Error",
+            r#"{"errors":[{"message":"internal error"}]}"#,
+        )]);
+        let mut client = Client::new();
+        client.endpoint = endpoint;
+
+        let result = client.get_post_data("a1b2c3d4e5f6");
+        assert!(
+            matches!(&result, Err(ClientError::GraphQlError(messages)) if messages == &["internal error"]),
+            "{:?}",
+            result.err()
+        );
+    }
+
+    #[test]
+    fn test_user_agent_is_sent() {
+        let (endpoint, received) = capturing_stub_server();
+        let mut client = Client::new().with_user_agent(String::from("Mozilla/5.0 (test)"));
+        client.endpoint = endpoint;
+
+        client.get_post_data("a1b2c3d4e5f6").unwrap();
+        let request = received.recv().unwrap().to_lowercase();
+        assert!(
+            request.contains("user-agent: mozilla/5.0 (test)\r\n"),
+            "{}",
+            request
+        );
+    }
+
+    #[test]
+    fn test_auth_token_is_sent_as_cookie() {
+        let (endpoint, received) = capturing_stub_server();
+        let client = Client::new()
+            .with_endpoint(endpoint)
+            .with_auth_token("secret-session");
+
+        client.get_post_data("a1b2c3d4e5f6").unwrap();
+        let request = received.recv().unwrap().to_lowercase();
+        assert!(
+            request.contains("cookie: sid=secret-session\r\n"),
+            "{}",
+            request
+        );
+    }
+
+    #[test]
+    fn test_no_cookie_without_auth_token() {
+        let (endpoint, received) = capturing_stub_server();
+        let client = Client::builder().endpoint(endpoint).build();
+
+        client.get_post_data("a1b2c3d4e5f6").unwrap();
+        let request = received.recv().unwrap().to_lowercase();
+        assert!(!request.contains("cookie:"), "{}", request);
+    }
+
+    #[test]
+    fn test_builder_configures_client() {
+        let (endpoint, received) = capturing_stub_server();
+        let client = Client::builder()
+            .endpoint(endpoint.replace("/_/graphql", "/mirror/graphql"))
+            .user_agent("builder-test")
+            .timeout(Duration::from_secs(5))
+            .retries(2)
+            .build();
+
+        let post = client.get_post_data("a1b2c3d4e5f6").unwrap().get_post();
+        assert_eq!("A Sample Post", post.title);
+        assert_eq!(2, client.max_retries);
+        let request = received.recv().unwrap();
+        assert!(request.starts_with("POST /mirror/graphql "), "{}", request);
+        assert!(request.contains("builder-test"), "{}", request);
+    }
+
+    #[test]
+    fn test_endpoint_defaults_to_medium() {
+        assert_eq!(GRAPHQL_ENDPOINT, Client::new().endpoint);
+        assert_eq!(GRAPHQL_ENDPOINT, Client::builder().build().endpoint);
+    }
+
+    #[test]
+    fn test_default_user_agent_names_the_crate() {
+        assert!(Client::new().user_agent.starts_with("mediumrare/"));
+    }
+
+    #[test]
+    fn test_retries_transient_server_errors() {
+        let endpoint = stub_responses(vec![
+            http_response("503 Service Unavailable", ""),
+            http_response("502 Bad Gateway", ""),
+            http_response("200 OK", include_str!("../test/post.json")),
+        ]);
+        let mut client = Client::new().with_retries(2);
+        client.endpoint = endpoint;
+        client.retry_backoff = Duration::from_millis(1);
+
+        let post = client.get_post_data("a1b2c3d4e5f6").unwrap().get_post();
+        assert_eq!("A Sample Post", post.title);
+    }
+
+    #[test]
+    fn test_user_post_ids_are_requested_by_username() {
+        let (sender, received) = mpsc::channel();
+        let endpoint = stub_server(move |mut stream| {
+            // the body may arrive in a separate segment after the headers
+            let mut request = String::new();
+            let mut buffer = [0; 4096];
+            while !request.ends_with('}') {
+                let length = stream.read(&mut buffer).unwrap();
+                request.push_str(&String::from_utf8_lossy(&buffer[..length]));
+            }
+            sender.send(request).unwrap();
+            write!(
+                stream,
+                "{}",
+                http_response(
+                    "200 OK",
+                    r#"{"data":{"userResult":{"__typename":"User","homepagePostsConnection":{"posts":[{"id":"a1b2c3d4e5f6"},{"id":"0123456789ab"}]}}}}"#
+                )
+            )
+            .unwrap();
+        });
+        let client = Client::new().with_endpoint(endpoint);
+
+        let post_ids = client.get_user_post_ids("@jdoe", 2).unwrap();
+        assert_eq!(vec!["a1b2c3d4e5f6", "0123456789ab"], post_ids);
+        let request = received.recv().unwrap();
+        assert!(
+            request.contains(r#""variables":{"limit":2,"username":"jdoe"}"#),
+            "{}",
+            request
+        );
+    }
+
+    #[test]
+    fn test_unknown_user_is_not_found() {
+        let result = parse_user_posts_response(
+            "nobody",
+            r#"{"data":{"userResult":{"__typename":"NotFound"}}}"#,
+        );
+        assert!(matches!(result, Err(ClientError::NotFoundError(name)) if name == "nobody"));
+    }
+
+    #[test]
+    fn test_resolve_post_id_follows_redirects() {
+        let endpoint = stub_responses(vec![
+            String::from(concat!(
+                "HTTP/1.1 301 Moved Permanently\r\n",
+                "Location: /@jdoe/a-sample-post-a1b2c3d4e5f6\r\n",
+                "Content-Length: 0\r\nConnection: close\r\n\r\n"
+            )),
+            http_response("200 OK", "<html></html>"),
+        ]);
+        let client = Client::new();
+
+        let post_id = client
+            .resolve_post_id(&endpoint.replace("/_/graphql", "/@jdoe/a-sample-post"))
+            .unwrap();
+        assert_eq!("a1b2c3d4e5f6", post_id);
+    }
+
+    #[test]
+    fn test_resolve_post_id_reads_canonical_link() {
+        let endpoint = stub_responses(vec![http_response(
+            "200 OK",
+            r#"<html><head><link rel="canonical" href="https://medium.com/@jdoe/a-sample-post-a1b2c3d4e5f6"></head></html>"#,
+        )]);
+        let client = Client::new();
+
+        let post_id = client
+            .resolve_post_id(&endpoint.replace("/_/graphql", "/@jdoe/a-sample-post"))
+            .unwrap();
+        assert_eq!("a1b2c3d4e5f6", post_id);
+    }
+
+    #[test]
+    fn test_resolve_post_id_without_id_anywhere() {
+        let endpoint = stub_responses(vec![http_response("200 OK", "<html></html>")]);
+        let result = Client::new().resolve_post_id(&endpoint.replace("/_/graphql", "/@jdoe"));
+        assert!(matches!(result, Err(ClientError::NotFoundError(_))));
+    }
+
+    #[test]
+    fn test_resolve_post_id_skips_the_request_when_the_url_has_an_id() {
+        let post_id = Client::new()
+            .resolve_post_id("https://medium.com/@jdoe/a-sample-post-a1b2c3d4e5f6")
+            .unwrap();
+        assert_eq!("a1b2c3d4e5f6", post_id);
+    }
+
+    #[test]
+    fn test_canonical_url() {
+        let html = r#"<link rel="icon" href="/favicon.ico"><link rel="canonical" href="https://medium.com/p/a1b2c3d4e5f6">"#;
+        assert_eq!(
+            Some("https://medium.com/p/a1b2c3d4e5f6"),
+            canonical_url(html)
+        );
+        assert_eq!(
+            None,
+            canonical_url(r#"<link rel="icon" href="/favicon.ico">"#)
+        );
+    }
+
+    fn rate_limited_response(retry_after: &str) -> String {
+        format!(
+            "HTTP/1.1 429 Too Many Requests\r\nRetry-After: {}\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+            retry_after
+        )
+    }
+
+    #[test]
+    fn test_rate_limit_reports_retry_after() {
+        let endpoint = stub_responses(vec![rate_limited_response("2")]);
+        let client = Client::new().with_endpoint(endpoint);
+
+        let result = client.get_post_data("a1b2c3d4e5f6");
+        assert!(
+            matches!(
+                result,
+                Err(ClientError::RateLimited { retry_after }) if retry_after == Duration::from_secs(2)
+            ),
+            "{:?}",
+            result.err()
+        );
+    }
+
+    #[test]
+    fn test_rate_limit_is_waited_out_when_retrying() {
+        let endpoint = stub_responses(vec![
+            rate_limited_response("0"),
+            http_response("200 OK", include_str!("../test/post.json")),
+        ]);
+        let client = Client::new().with_endpoint(endpoint).with_retries(1);
+
+        let post = client.get_post_data("a1b2c3d4e5f6").unwrap().get_post();
+        assert_eq!("A Sample Post", post.title);
+    }
+
+    #[test]
+    fn test_long_retry_after_is_not_waited_out() {
+        let endpoint = stub_responses(vec![rate_limited_response("120")]);
+        let client = Client::new().with_endpoint(endpoint).with_retries(3);
+
+        let result = client.get_post_data("a1b2c3d4e5f6");
+        assert!(
+            matches!(
+                result,
+                Err(ClientError::RateLimited { retry_after }) if retry_after == Duration::from_secs(120)
+            ),
+            "{:?}",
+            result.err()
+        );
+    }
+
+    #[test]
+    fn test_retry_after_parsing() {
+        assert_eq!(Some(Duration::from_secs(2)), parse_retry_after(" 2"));
+        assert_eq!(None, parse_retry_after("Wed, 21 Oct 2015 07:28:00 GMT"));
+    }
+
+    #[test]
+    fn test_gives_up_after_max_retries() {
+        let endpoint = stub_responses(vec![
+            http_response("503 Service Unavailable", ""),
+            http_response("503 Service Unavailable", ""),
+        ]);
+        let mut client = Client::new().with_retries(1);
+        client.endpoint = endpoint;
+        client.retry_backoff = Duration::from_millis(1);
+
+        let result = client.get_post_data("a1b2c3d4e5f6");
+        assert!(
+            matches!(
+                result,
+                Err(ClientError::RequestError(ureq::Error::StatusCode(503)))
+            ),
+            "{:?}",
+            result.err()
+        );
+    }
+
+    #[test]
+    fn test_retry_delay_doubles_up_to_the_cap() {
+        let backoff = Duration::from_millis(200);
+
+        assert_eq!(backoff, super::retry_delay(backoff, 0));
+        assert_eq!(Duration::from_millis(800), super::retry_delay(backoff, 2));
+        assert_eq!(super::MAX_RETRY_BACKOFF, super::retry_delay(backoff, 10));
+        assert_eq!(
+            super::MAX_RETRY_BACKOFF,
+            super::retry_delay(backoff, u32::MAX)
+        );
+    }
+
+    #[test]
+    fn test_many_retries_do_not_overflow() {
+        let mut responses = vec![http_response("503 Service Unavailable", ""); 40];
+        responses.push(http_response("200 OK", include_str!("../test/post.json")));
+        let endpoint = stub_responses(responses);
+        let mut client = Client::new().with_retries(40);
+        client.endpoint = endpoint;
+        client.retry_backoff = Duration::ZERO;
+
+        let post = client.get_post_data("a1b2c3d4e5f6").unwrap().get_post();
+        assert_eq!("A Sample Post", post.title);
+    }
+
+    #[test]
+    fn test_raw_response_is_returned_unparsed() {
+        let body = r#"{"data":{"postResult":{"id":"a1b2c3d4e5f6","unmodeled":[1,2]}}}"#;
+        let endpoint = stub_responses(vec![http_response("200 OK", body)]);
+        let mut client = Client::new();
+        client.endpoint = endpoint;
+
+        assert_eq!(body, client.get_post_data_raw("a1b2c3d4e5f6").unwrap());
+    }
+
+    #[test]
+    fn test_not_found_is_not_retried() {
+        // the stub only answers once, a retry would fail to connect
+        let endpoint = stub_responses(vec![http_response("404 Not Found", "")]);
+        let mut client = Client::new().with_retries(3);
+        client.endpoint = endpoint;
+        client.retry_backoff = Duration::from_millis(1);
+
+        let result = client.get_post_data("a1b2c3d4e5f6");
+        assert!(matches!(result, Err(ClientError::NotFoundError(_))));
+    }
+
+    #[tokio::test]
+    async fn test_spawn_blocking_client_fetches_async() {
+        let endpoint = stub_responses(vec![http_response(
+            "200 OK",
+            include_str!("../test/post.json"),
+        )]);
+        let mut client = Client::new();
+        client.endpoint = endpoint;
+        let client = SpawnBlockingClient::new(client);
+
+        let post = AsyncPostDataClient::get_post_data(&client, "a1b2c3d4e5f6")
+            .await
+            .unwrap()
+            .get_post();
+        assert_eq!("A Sample Post", post.title);
+    }
+
+    #[test]
+    fn test_summary_falls_back_to_bio() {
+        let post = serde_json::from_value::<QueryResponse>(sample_response())
+            .unwrap()
+            .get_post();
+        assert_eq!("This is the first paragraph.", post.summary());
+
+        let mut json = sample_response();
+        json["data"]["postResult"]["content"] = Value::Null;
+        let post = serde_json::from_value::<QueryResponse>(json)
+            .unwrap()
+            .get_post();
+        assert_eq!(post.creator.bio, post.summary());
     }
 }
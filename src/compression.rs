@@ -0,0 +1,92 @@
+//! compresses responses with the best encoding the client accepts. bodies below
+//! `MIN_COMPRESSED_LEN` are sent as-is, the headers would eat most of the savings.
+
+use std::io::Write;
+
+pub const MIN_COMPRESSED_LEN: usize = 1024;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Encoding {
+    Brotli,
+    Gzip,
+}
+
+impl Encoding {
+    /// the `Content-Encoding` header value
+    pub fn name(&self) -> &'static str {
+        match self {
+            Encoding::Brotli => "br",
+            Encoding::Gzip => "gzip",
+        }
+    }
+
+    pub fn compress(&self, data: &[u8]) -> Vec<u8> {
+        let mut out = Vec::new();
+        match self {
+            Encoding::Brotli => {
+                let mut writer = brotli::CompressorWriter::new(&mut out, 4096, 5, 22);
+                writer.write_all(data).expect("writing to a vec can't fail");
+            }
+            Encoding::Gzip => {
+                let mut encoder =
+                    flate2::write::GzEncoder::new(&mut out, flate2::Compression::default());
+                encoder
+                    .write_all(data)
+                    .expect("writing to a vec can't fail");
+                encoder.finish().expect("writing to a vec can't fail");
+            }
+        }
+        out
+    }
+}
+
+/// whether `Accept-Encoding` lists `name` with a non-zero quality
+fn accepts(accept_encoding: &str, name: &str) -> bool {
+    accept_encoding.split(',').any(|entry| {
+        let mut parts = entry.split(';').map(str::trim);
+        let coding = parts.next().unwrap_or_default();
+        let quality = parts
+            .find_map(|param| param.strip_prefix("q="))
+            .map_or(1.0, |q| q.parse().unwrap_or(0.0));
+        (coding.eq_ignore_ascii_case(name) || coding == "*") && quality > 0.0
+    })
+}
+
+/// picks brotli over gzip, `None` when the client accepts neither
+pub fn negotiate(accept_encoding: &str) -> Option<Encoding> {
+    [Encoding::Brotli, Encoding::Gzip]
+        .into_iter()
+        .find(|encoding| accepts(accept_encoding, encoding.name()))
+}
+
+#[cfg(test)]
+mod test {
+    use crate::compression::{negotiate, Encoding};
+    use std::io::Read;
+
+    #[test]
+    fn test_negotiate_prefers_brotli() {
+        assert_eq!(Some(Encoding::Brotli), negotiate("gzip, deflate, br"));
+        assert_eq!(Some(Encoding::Gzip), negotiate("gzip, deflate"));
+        assert_eq!(Some(Encoding::Gzip), negotiate("br;q=0, GZIP;q=0.5"));
+        assert_eq!(None, negotiate("identity"));
+        assert_eq!(None, negotiate(""));
+    }
+
+    #[test]
+    fn test_compressed_data_round_trips() {
+        let html = "<p>hello</p>".repeat(200);
+
+        let mut gunzipped = String::new();
+        flate2::read::GzDecoder::new(Encoding::Gzip.compress(html.as_bytes()).as_slice())
+            .read_to_string(&mut gunzipped)
+            .unwrap();
+        assert_eq!(html, gunzipped);
+
+        let mut unbrotlied = String::new();
+        brotli::Decompressor::new(Encoding::Brotli.compress(html.as_bytes()).as_slice(), 4096)
+            .read_to_string(&mut unbrotlied)
+            .unwrap();
+        assert_eq!(html, unbrotlied);
+    }
+}
@@ -1,13 +1,20 @@
 use crate::client;
-use crate::client::{Markup, PostResult};
+use crate::client::Markup;
+#[cfg(feature = "emoji")]
+use crate::emoji::{replace_emoji, EmojiMode};
+#[cfg(feature = "math")]
+use crate::math;
 use crate::text_markup::{SpanWrap, TextSpan};
+use crate::timestamp::{epoch_to_human, epoch_to_rfc3339};
 use anyhow::{Context, Result};
-use std::collections::HashMap;
+use std::cmp::Reverse;
+use std::collections::BTreeMap;
+use std::{fmt, io};
 
 macro_rules! attributes {
     ($($name:expr => $value:expr),+) => {
         {
-            let mut attributes = HashMap::<String, String>::new();
+            let mut attributes = BTreeMap::<String, String>::new();
             $(attributes.insert($name.to_string(), $value.to_string());)+
 
             attributes
@@ -19,40 +26,114 @@ pub enum Content {
     Text(String),
     Tag {
         name: String,
-        attributes: HashMap<String, String>,
+        /// sorted, so the same tree always serializes to the same html
+        attributes: BTreeMap<String, String>,
         children: Option<Vec<Content>>,
     },
 }
 
-impl ToString for Content {
-    fn to_string(&self) -> String {
+/// attribute values are escaped on output, unlike text nodes which are escaped
+/// when they are created
+pub(crate) fn escape_attribute(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('"', "&quot;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+fn serialize_attributes(attributes: &BTreeMap<String, String>) -> String {
+    attributes
+        .iter()
+        .map(|(name, value)| format!(r#" {}="{}""#, name, escape_attribute(value)))
+        .collect()
+}
+
+fn open_tag(name: &str, attributes: &BTreeMap<String, String>) -> String {
+    format!("<{}{}>", name, serialize_attributes(attributes))
+}
+
+/// the raw text of an escaped text node
+pub(crate) fn unescape_text(text: &str) -> String {
+    text.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&amp;", "&")
+}
+
+const PLAIN_TEXT_BLOCKS: [&str; 11] = [
+    "p",
+    "li",
+    "h1",
+    "h2",
+    "h3",
+    "h4",
+    "h5",
+    "h6",
+    "blockquote",
+    "pre",
+    "figcaption",
+];
+
+/// tags put on their own line by `Content::to_pretty_string`, everything else is
+/// kept inline with its siblings
+const PRETTY_BLOCKS: [&str; 20] = [
+    "article",
+    "section",
+    "header",
+    "footer",
+    "div",
+    "figure",
+    "ul",
+    "ol",
+    "dl",
+    "li",
+    "p",
+    "h1",
+    "h2",
+    "h3",
+    "h4",
+    "h5",
+    "h6",
+    "blockquote",
+    "pre",
+    "hr",
+];
+
+/// the html of the tree, written straight into the formatter
+impl fmt::Display for Content {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
-            Self::Text(t) => t.to_owned(),
+            Self::Text(t) => f.write_str(t),
             Self::Tag {
                 name,
                 attributes,
                 children,
             } => {
-                let attrs: String = attributes
-                    .iter()
-                    .map(|(name, value)| format!(r#"{}="{}" "#, name, value))
-                    .collect();
-
-                let child_html: Option<String> = children
-                    .as_ref()
-                    .map(|elements| elements.iter().map(|child| child.to_string()).collect());
+                write!(f, "<{}", name)?;
+                for (attribute, value) in attributes {
+                    write!(f, r#" {}="{}""#, attribute, escape_attribute(value))?;
+                }
 
-                if let Some(child_html) = child_html {
-                    format!("<{name} {}>{}</{name}>", attrs, child_html, name = name)
-                } else {
-                    format!("<{} {}/>", name, attrs)
+                let Some(children) = children else {
+                    return f.write_str("/>");
+                };
+                f.write_str(">")?;
+                for child in children {
+                    fmt::Display::fmt(child, f)?;
                 }
+                write!(f, "</{}>", name)
             }
         }
     }
 }
 
 impl Content {
+    /// serializes into `w` while walking the tree, without building the whole
+    /// document in memory first like `to_string` does
+    pub fn write_to<W: io::Write>(&self, w: &mut W) -> io::Result<()> {
+        write!(w, "{}", self)
+    }
+
     pub fn text<S: Into<String>>(txt: S) -> Content {
         Content::Text(
             txt.into()
@@ -65,10 +146,20 @@ impl Content {
     pub fn hyperlink<S: Into<String>>(
         href: S,
         children: Vec<Content>,
-        attr: Option<HashMap<String, String>>,
+        attr: Option<BTreeMap<String, String>>,
     ) -> Content {
+        let href = href.into();
         let mut attributes = attr.unwrap_or_default();
-        attributes.insert("href".into(), href.into());
+        // links leaving medium open in a new tab, without a handle back to our page
+        if link_host(&href).is_some_and(|host| !is_medium_host(&host)) {
+            attributes
+                .entry("target".into())
+                .or_insert_with(|| "_blank".into());
+            attributes
+                .entry("rel".into())
+                .or_insert_with(|| "noopener noreferrer".into());
+        }
+        attributes.insert("href".into(), href);
         Content::Tag {
             name: "a".into(),
             attributes,
@@ -78,7 +169,7 @@ impl Content {
 
     pub fn tag<S: Into<String>>(
         name: S,
-        attr: Option<HashMap<String, String>>,
+        attr: Option<BTreeMap<String, String>>,
         children: Option<Vec<Content>>,
     ) -> Content {
         Content::Tag {
@@ -87,54 +178,567 @@ impl Content {
             children,
         }
     }
+
+    /// whether this node or any of its children carries `class`
+    pub fn contains_class(&self, class: &str) -> bool {
+        match self {
+            Self::Text(_) => false,
+            Self::Tag {
+                attributes,
+                children,
+                ..
+            } => {
+                attributes.get("class").map(String::as_str) == Some(class)
+                    || children
+                        .as_ref()
+                        .is_some_and(|children| children.iter().any(|c| c.contains_class(class)))
+            }
+        }
+    }
+
+    /// every `href` in the tree in document order, e.g. to check the links of a post
+    pub fn collect_links(&self) -> Vec<String> {
+        let mut links = Vec::new();
+        self.push_links(&mut links);
+        links
+    }
+
+    fn push_links(&self, links: &mut Vec<String>) {
+        if let Self::Tag {
+            attributes,
+            children,
+            ..
+        } = self
+        {
+            links.extend(attributes.get("href").cloned());
+            for child in children.iter().flatten() {
+                child.push_links(links);
+            }
+        }
+    }
+
+    /// like `to_string`, but every block level tag starts on its own line, indented
+    /// by `indent` spaces per nesting level. tags with only inline children stay on
+    /// one line and text is never reflowed, so `pre` blocks keep their content.
+    pub fn to_pretty_string(&self, indent: usize) -> String {
+        let mut out = String::new();
+        self.push_pretty(indent, 0, &mut out);
+        out
+    }
+
+    fn is_pretty_block(&self) -> bool {
+        matches!(self, Self::Tag { name, .. } if PRETTY_BLOCKS.contains(&name.as_str()))
+    }
+
+    fn push_pretty(&self, indent: usize, level: usize, out: &mut String) {
+        out.push_str(&" ".repeat(indent * level));
+        let (name, attributes, children) = match self {
+            Self::Tag {
+                name,
+                attributes,
+                children: Some(children),
+            } if name.as_str() != "pre" && children.iter().any(Content::is_pretty_block) => {
+                (name, attributes, children)
+            }
+            _ => {
+                out.push_str(&self.to_string());
+                out.push('\n');
+                return;
+            }
+        };
+
+        out.push_str(&open_tag(name, attributes));
+        out.push('\n');
+        for child in children {
+            child.push_pretty(indent, level + 1, out);
+        }
+        out.push_str(&" ".repeat(indent * level));
+        out.push_str(&format!("</{}>\n", name));
+    }
+
+    /// applies `f` to every text node in this tree
+    pub fn map_text(&mut self, f: &dyn Fn(&str) -> String) {
+        match self {
+            Self::Text(t) => *t = f(t),
+            Self::Tag {
+                children: Some(children),
+                ..
+            } => children.iter_mut().for_each(|child| child.map_text(f)),
+            Self::Tag { children: None, .. } => {}
+        }
+    }
+
+    /// the readable text of this tree, block level tags end up on their own lines
+    pub fn to_plain_text(&self) -> String {
+        let mut out = String::new();
+        self.push_plain_text(&mut out);
+        out.trim().to_string()
+    }
+
+    fn push_plain_text(&self, out: &mut String) {
+        let (name, attributes, children) = match self {
+            Self::Text(text) => return out.push_str(&unescape_text(text)),
+            Self::Tag {
+                name,
+                attributes,
+                children,
+            } => (name.as_str(), attributes, children),
+        };
+
+        match name {
+            "img" => out.push_str(attributes.get("alt").map_or("", String::as_str)),
+            "iframe" => out.push_str(attributes.get("title").map_or("", String::as_str)),
+            "br" => out.push('\n'),
+            _ => {
+                let block = PLAIN_TEXT_BLOCKS.contains(&name);
+                if block && !out.is_empty() && !out.ends_with('\n') {
+                    out.push('\n');
+                }
+                children
+                    .iter()
+                    .flatten()
+                    .for_each(|child| child.push_plain_text(out));
+                if block && !out.ends_with('\n') {
+                    out.push('\n');
+                }
+            }
+        }
+    }
+
+    pub fn tag_with_class<S: Into<String>, C: Into<String>>(
+        name: S,
+        class: C,
+        children: Option<Vec<Content>>,
+    ) -> Content {
+        Content::tag(name, Some(attributes!("class" => class.into())), children)
+    }
 }
 
+/// renders `text` with its markups, newlines become `<br/>` since html collapses them
 fn render_text(text: &str, markups: &[Markup]) -> Result<Vec<Content>> {
-    if markups.is_empty() {
+    Ok(break_lines(render_spans(text, markups)?))
+}
+
+/// splits text nodes on newlines. markup offsets count the newlines, so this only
+/// runs once the spans are resolved
+fn break_lines(contents: Vec<Content>) -> Vec<Content> {
+    contents
+        .into_iter()
+        .flat_map(|content| match content {
+            Content::Text(text) if text.contains('\n') => {
+                let mut lines = Vec::new();
+                for (index, line) in text.split('\n').enumerate() {
+                    if index > 0 {
+                        lines.push(Content::tag("br", None, None));
+                    }
+                    if !line.is_empty() {
+                        lines.push(Content::Text(line.to_string()));
+                    }
+                }
+                lines
+            }
+            Content::Tag {
+                name,
+                attributes,
+                children,
+            } => vec![Content::Tag {
+                name,
+                attributes,
+                children: children.map(break_lines),
+            }],
+            text => vec![text],
+        })
+        .collect()
+}
+
+fn render_spans(text: &str, markups: &[Markup]) -> Result<Vec<Content>> {
+    // medium sometimes sends markups starting past the end of the text, there is
+    // nothing left for them to wrap. ends past the text are clamped by the spans.
+    let text_len = text.encode_utf16().count();
+    let mut sorted_markup: Vec<Markup> = markups
+        .iter()
+        .filter(|m| m.start < text_len && m.start <= m.end)
+        .cloned()
+        .collect();
+    if sorted_markup.is_empty() {
         return Ok(vec![Content::text(text)]);
     }
 
     let mut span = TextSpan::create(text);
 
-    // temp workaround, we should find a better way to handle this
-    let mut sorted_markup = Vec::from(markups);
-    sorted_markup.sort_by(|l, r| (r.end - r.start).cmp(&(l.end - l.start)));
+    // longer markups first, so markups inside them nest instead of splitting them
+    sorted_markup.sort_by_key(|m| Reverse(m.end - m.start));
 
     for markup in &sorted_markup {
-        let subspan = span
-            .get_sub_span_mut(markup.start, markup.end)
-            .context(format!("failed to get span for markup {:?}", markup))?;
         let wrap = match markup.r#type.as_str() {
             "STRONG" => SpanWrap::Strong,
             "CODE" => SpanWrap::Code,
             "EM" => SpanWrap::Emphasized,
+            "STRIKE" | "DEL" => SpanWrap::Strikethrough,
+            "SUP" | "SUPERSCRIPT" => SpanWrap::Superscript,
+            "SUB" | "SUBSCRIPT" => SpanWrap::Subscript,
             "A" => SpanWrap::Link {
                 href: markup.href.as_ref().unwrap_or(&"".to_string()).to_string(),
             },
-            _ => panic!("unknown markup type {}", markup.r#type),
+            "USER" => match &markup.user_id {
+                Some(user_id) => SpanWrap::Mention {
+                    user_id: user_id.clone(),
+                },
+                None => continue,
+            },
+            // a new kind of markup shouldn't take the whole page down, keep the text
+            _ => {
+                tracing::warn!(markup_type = %markup.r#type, "unknown markup");
+                continue;
+            }
         };
 
-        subspan.add_wrap(wrap);
+        span.wrap_range(markup.start, markup.end, &wrap);
     }
 
     Ok(span.into())
 }
 
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum QuoteKind {
+    Block,
+    Pull,
+}
+
+impl QuoteKind {
+    fn class(&self) -> Option<&'static str> {
+        match self {
+            QuoteKind::Block => None,
+            QuoteKind::Pull => Some("pullquote"),
+        }
+    }
+}
+
+fn render_quote(kind: QuoteKind, paragraph: &client::Paragraph) -> Result<Content> {
+    let children = Some(
+        render_text(
+            paragraph.text.as_ref().map_or("", |t| t.as_str()),
+            &paragraph.markups,
+        )
+        .context(format!("on rendering a {:?} quote", kind))?,
+    );
+
+    Ok(match kind.class() {
+        Some(class) => Content::tag_with_class("blockquote", class, children),
+        None => Content::tag("blockquote", None, children),
+    })
+}
+
+/// medium wraps most embeds in an embedly url with the original url percent encoded
+fn decode_iframe_src(iframe_src: &str) -> String {
+    iframe_src
+        .replace("%3A", ":")
+        .replace("%2F", "/")
+        .replace("%3F", "?")
+        .replace("%3D", "=")
+        .replace("%26", "&")
+}
+
+/// finds the tweet an embed points at, medium usually wraps them in an
+/// url-encoded embedly link
+fn tweet_url(iframe_src: &str) -> Option<String> {
+    let decoded = decode_iframe_src(iframe_src);
+
+    let start = ["https://twitter.com/", "https://x.com/"]
+        .iter()
+        .filter_map(|host| decoded.find(host))
+        .min()?;
+    let url = decoded[start..]
+        .split(['&', '?', '"'])
+        .next()
+        .unwrap_or_default();
+
+    url.contains("/status/").then(|| url.to_string())
+}
+
+/// players of hosts that are safe to embed directly
+const EMBED_PREFIXES: [&str; 3] = [
+    "https://www.youtube.com/embed/",
+    "https://www.youtube-nocookie.com/embed/",
+    "https://player.vimeo.com/video/",
+];
+
+fn embed_url(iframe_src: &str) -> Option<String> {
+    let decoded = decode_iframe_src(iframe_src);
+
+    let start = EMBED_PREFIXES
+        .iter()
+        .filter_map(|prefix| decoded.find(prefix))
+        .min()?;
+    decoded[start..].split(['&', '"']).next().map(String::from)
+}
+
+/// the size medium reports for an embed, 16:9 when it reports none
+fn iframe_dimensions(media: &client::IFrameMediaResource) -> (usize, usize) {
+    if media.iframe_width > 0 && media.iframe_height > 0 {
+        (media.iframe_width, media.iframe_height)
+    } else {
+        (640, 360)
+    }
+}
+
+fn render_embed(url: String, media: &client::IFrameMediaResource) -> Content {
+    let (width, height) = iframe_dimensions(media);
+    // the stylesheet scales embeds to the article width, the aspect ratio keeps
+    // them from collapsing
+    let attr = attributes! {
+        "src" => url,
+        "title" => media.title,
+        "loading" => "lazy",
+        "frameborder" => "0",
+        "allowfullscreen" => "",
+        "width" => width,
+        "height" => height,
+        "style" => format!("aspect-ratio: {} / {}", width, height)
+    };
+
+    Content::tag_with_class(
+        "div",
+        "embed",
+        Some(vec![Content::tag("iframe", Some(attr), Some(vec![]))]),
+    )
+}
+
+fn render_iframe(paragraph: &client::Paragraph, options: &RenderOptions) -> Content {
+    let media = &paragraph.iframe.as_ref().unwrap().media_resource;
+
+    if let Some(url) = tweet_url(&media.iframe_src) {
+        let link = Content::hyperlink(url, vec![Content::text(media.title.clone())], None);
+        return if options.twitter_widget {
+            Content::tag_with_class("blockquote", "twitter-tweet", Some(vec![link]))
+        } else {
+            Content::tag_with_class("p", "tweet", Some(vec![Content::text("Tweet: "), link]))
+        };
+    }
+
+    if let Some(url) = embed_url(&media.iframe_src) {
+        return render_embed(url, media);
+    }
+
+    let attr = (media.iframe_width > 0 && media.iframe_height > 0).then(|| {
+        attributes! {
+            "data-width" => media.iframe_width,
+            "data-height" => media.iframe_height
+        }
+    });
+    Content::hyperlink(
+        media.iframe_src.clone(),
+        vec![
+            Content::text("IFRAME: "),
+            Content::text(media.title.clone()),
+        ],
+        attr,
+    )
+}
+
+/// swaps in the fallback image once when the original fails to load. quotes are
+/// percent encoded so the url can't break out of the js string or the attribute.
+fn fallback_onerror(fallback_url: &str) -> String {
+    let url = fallback_url
+        .replace('\\', "%5C")
+        .replace('\'', "%27")
+        .replace('"', "%22");
+    format!("this.onerror=null;this.src='{}';", url)
+}
+
+/// images are wrapped in a figure, with their caption when medium sent one
+fn render_figure(paragraph: &client::Paragraph, options: &RenderOptions) -> Result<Content> {
+    let mut children = vec![render_image(paragraph, options)];
+    let caption = paragraph.text.as_deref().unwrap_or_default();
+    if !caption.is_empty() {
+        children.push(Content::tag(
+            "figcaption",
+            None,
+            Some(render_text(caption, &paragraph.markups).context("on rendering IMG caption")?),
+        ));
+    }
+
+    Ok(Content::tag("figure", None, Some(children)))
+}
+
+fn render_image(paragraph: &client::Paragraph, options: &RenderOptions) -> Content {
+    let metadata = paragraph.metadata.as_ref().unwrap();
+    let mut attr = attributes! {
+        "src" => image_url(&metadata.id, options),
+        "alt" => metadata.alt.as_deref().unwrap_or_default(),
+        "loading" => "lazy",
+        "decoding" => "async",
+        "fetchpriority" => "low"
+    };
+    insert_srcset(&mut attr, &metadata.id, options);
+    // reserving the space up front avoids the page jumping around while loading
+    if let (Some(width), Some(height)) = (metadata.original_width, metadata.original_height) {
+        if width > 0 && height > 0 {
+            attr.insert("width".into(), width.to_string());
+            attr.insert("height".into(), height.to_string());
+        }
+    }
+    if let Some(fallback) = &options.fallback_image {
+        attr.insert("onerror".into(), fallback_onerror(fallback));
+    }
+
+    Content::tag("img", Some(attr), None)
+}
+
+fn render_body_text(paragraph: &client::Paragraph) -> Result<Content> {
+    let text = paragraph.text.as_ref().map_or("", |t| t.as_str());
+
+    #[cfg(feature = "math")]
+    if let Some(formula) = math::block_math(text) {
+        return Ok(math::math_block(formula));
+    }
+
+    let children = render_text(text, &paragraph.markups)?;
+    #[cfg(feature = "math")]
+    let children = math::render_inline_math(children);
+
+    Ok(Content::tag("p", None, Some(children)))
+}
+
+/// code blocks keep their text exactly as sent, the only transformation applied
+/// is the optional tab expansion. whitespace is preserved by `white-space: pre`.
+fn render_preformatted(paragraph: &client::Paragraph, options: &RenderOptions) -> Result<Content> {
+    let mut children = render_spans(
+        paragraph.text.as_ref().map_or("", |t| t.as_str()),
+        &paragraph.markups,
+    )
+    .context("on rendering PRE tag")?;
+
+    if let Some(width) = options.tab_width {
+        let spaces = " ".repeat(width);
+        for child in children.iter_mut() {
+            child.map_text(&|text| text.replace('\t', &spaces));
+        }
+    }
+
+    // highlight.js and prism pick the language up from the class of the inner code
+    let lang = paragraph
+        .code_block_metadata
+        .as_ref()
+        .and_then(|metadata| metadata.lang.as_deref())
+        .filter(|lang| !lang.is_empty());
+    let code = match lang {
+        Some(lang) => Content::tag_with_class(
+            "code",
+            format!("language-{}", lang.to_lowercase()),
+            Some(children),
+        ),
+        None => Content::tag("code", None, Some(children)),
+    };
+
+    Ok(Content::tag("pre", None, Some(vec![code])))
+}
+
+/// options that change how a post is rendered, the default renders the full post
+#[derive(Debug, Clone, Default)]
+pub struct RenderOptions {
+    /// only render the first n paragraphs of the body, followed by a "read more" link
+    pub preview_limit: Option<usize>,
+    /// replace tabs in code blocks with this many spaces, tabs are kept as-is when unset
+    pub tab_width: Option<usize>,
+    /// point images at our own `/img/` proxy route instead of the medium cdn
+    pub image_proxy: bool,
+    /// the width medium's cdn resizes images to, `DEFAULT_IMAGE_WIDTH` when unset
+    pub image_width: Option<u32>,
+    /// prefix headings with their section number, e.g. "1.2"
+    pub number_headings: bool,
+    /// render tweets as `twitter-tweet` blockquotes for twitter's widgets.js,
+    /// otherwise they are plain links
+    pub twitter_widget: bool,
+    /// image shown in place of medium images that fail to load
+    pub fallback_image: Option<String>,
+    /// mark abbreviations defined in the text, like "Cascading Style Sheets (CSS)",
+    /// with `<abbr>` and list their expansions at the end of the article
+    pub abbreviation_glossary: bool,
+    /// append the destination domain after external links, e.g. "(example.com)"
+    pub annotate_link_domains: bool,
+    /// what to do with emoji in the post text
+    #[cfg(feature = "emoji")]
+    pub emoji: EmojiMode,
+}
+
+pub const IMAGE_PROXY_PREFIX: &str = "img/";
+pub const DEFAULT_IMAGE_WIDTH: u32 = 2000;
+/// proxied widths are capped, so the proxy can't be used to fetch huge renditions
+pub const MAX_IMAGE_WIDTH: u32 = 4000;
+/// the sizes browsers can pick from via `srcset`. medium's cdn never upscales, so
+/// small originals are just served at their own size for the larger ones
+const SRCSET_WIDTHS: [u32; 3] = [640, 1000, 2000];
+/// articles are at most 60rem wide, see `html::STYLESHEET`
+const IMAGE_SIZES: &str = "(max-width: 60rem) 100vw, 60rem";
+
+fn cdn_image_url(id: &str, width: u32) -> String {
+    format!("https://miro.medium.com/v2/resize:fit:{}/{}", width, id)
+}
+
+fn proxied_image_url(id: &str, width: u32) -> String {
+    format!("/{}{}/{}", IMAGE_PROXY_PREFIX, width, id)
+}
+
+/// builds the url an image with the given medium id is loaded from. medium image
+/// ids are content addressed, so the proxied url never changes for an image and
+/// width and can be cached forever.
+pub fn image_url(id: &str, options: &RenderOptions) -> String {
+    let width = options.image_width.unwrap_or(DEFAULT_IMAGE_WIDTH);
+    if options.image_proxy {
+        proxied_image_url(id, width)
+    } else {
+        cdn_image_url(id, width)
+    }
+}
+
+/// the width and image id of a proxied image path behind `IMAGE_PROXY_PREFIX`, the
+/// counterpart of `image_url`. paths without a width get the default one
+pub fn parse_proxied_image(path: &str) -> (u32, &str) {
+    match path.split_once('/') {
+        Some((width, id)) => match width.parse::<u32>() {
+            Ok(width) => (width.clamp(1, MAX_IMAGE_WIDTH), id),
+            Err(_) => (DEFAULT_IMAGE_WIDTH, path),
+        },
+        None => (DEFAULT_IMAGE_WIDTH, path),
+    }
+}
+
+/// the `srcset` offering the image in several widths, through the proxy when
+/// images are proxied
+pub fn image_srcset(id: &str, options: &RenderOptions) -> String {
+    SRCSET_WIDTHS
+        .iter()
+        .map(|width| {
+            let url = if options.image_proxy {
+                proxied_image_url(id, *width)
+            } else {
+                cdn_image_url(id, *width)
+            };
+            format!("{} {}w", url, width)
+        })
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+fn insert_srcset(attributes: &mut BTreeMap<String, String>, id: &str, options: &RenderOptions) {
+    attributes.insert("srcset".into(), image_srcset(id, options));
+    attributes.insert("sizes".into(), IMAGE_SIZES.into());
+}
+
 pub trait Render {
-    fn render(&self) -> Result<Content>;
+    fn render(&self) -> Result<Content> {
+        self.render_with(&RenderOptions::default())
+    }
+
+    fn render_with(&self, options: &RenderOptions) -> Result<Content>;
 }
 
 impl Render for client::Paragraph {
-    fn render(&self) -> Result<Content> {
+    fn render_with(&self, options: &RenderOptions) -> Result<Content> {
         Ok(match self.r#type.as_str() {
-            "IMG" => {
-                let attr = Some(attributes! {
-                    "src" => format!("https://miro.medium.com/v2/resize:fit:2000/{}",self.metadata.as_ref().unwrap().id),
-                    "loading" => "lazy"
-                });
-                Content::tag("img", attr, None)
-            }
-            // TODO: wrap these in a grouping <ul> or <ol> tag
+            "IMG" => render_figure(self, options)?,
+            // grouped into their <ul> or <ol> by `group_list_items`
             "ULI" | "OLI" => Content::tag(
                 "li",
                 None,
@@ -143,33 +747,14 @@ impl Render for client::Paragraph {
                         .context("on rendering LI tag")?,
                 ),
             ),
-            "IFRAME" => {
-                let attr = Some(attributes! {
-                    "href" => self.iframe
-                        .as_ref()
-                        .unwrap()
-                        .media_resource
-                        .iframe_src
-                        .clone()
-                });
-                Content::tag(
-                    "a",
-                    attr,
-                    Some(vec![
-                        Content::text("IFRAME: "),
-                        Content::text(self.iframe.as_ref().unwrap().media_resource.title.clone()),
-                    ]),
-                )
-            }
-            "BQ" => Content::tag(
-                "blockquote",
-                None,
-                Some(render_text(
-                    self.text.as_ref().map_or("", |t| t.as_str()),
-                    &self.markups,
-                )?),
-            ),
-            "P" | "H1" | "H2" | "H3" | "H4" | "H5" | "H6" | "PRE" => Content::tag(
+            "IFRAME" => render_iframe(self, options),
+            // dividers carry no text worth keeping
+            "HR" => Content::tag("hr", None, None),
+            "BQ" => render_quote(QuoteKind::Block, self)?,
+            "PQ" => render_quote(QuoteKind::Pull, self)?,
+            "PRE" => render_preformatted(self, options)?,
+            "P" => render_body_text(self)?,
+            "H1" | "H2" | "H3" | "H4" | "H5" | "H6" => Content::tag(
                 self.r#type.to_lowercase(),
                 None,
                 Some(render_text(
@@ -193,113 +778,1928 @@ impl Render for client::Paragraph {
 }
 
 impl Render for client::PostResult {
-    fn render(&self) -> Result<Content> {
-        let mut content = self.render_header()?;
-        let mut body: Vec<Content> = self
-            .paragraphs()
+    fn render_with(&self, options: &RenderOptions) -> Result<Content> {
+        let mut content = self.render_header(options)?;
+        let paragraphs = preview_paragraphs(self.paragraphs(), options.preview_limit);
+        let mut body: Vec<Content> = paragraphs
             .iter()
-            .map(|p| p.render())
+            .map(|p| p.render_with(options))
             .collect::<Result<Vec<Content>>>()?;
+        if options.number_headings {
+            number_headings(paragraphs, &mut body);
+        }
+        // with a hero on top, every body image is below the fold
+        if self.preview_image_id().is_none() {
+            prioritize_lead_image(&mut body);
+        }
+        #[cfg(feature = "emoji")]
+        if options.emoji != EmojiMode::Keep {
+            for content in body.iter_mut() {
+                content.map_text(&|text| replace_emoji(text, options.emoji));
+            }
+        }
+        // grouping pairs `body` with `paragraphs` one to one, so annotating may
+        // only add nodes after it
+        let mut body = group_list_items(paragraphs, body);
+        if options.abbreviation_glossary {
+            mark_abbreviations(&mut body);
+        }
+        if options.annotate_link_domains {
+            annotate_link_domains(&mut body);
+        }
         content.append(&mut body);
+        if options.abbreviation_glossary {
+            content.extend(render_glossary(&content));
+        }
+        if paragraphs.len() < self.paragraphs().len() {
+            content.push(self.render_read_more());
+        }
+        content.push(self.render_author());
+        content.extend(self.render_footer());
         Ok(Content::tag("article", None, Some(content)))
     }
 }
 
-impl client::PostResult {
-    fn render_header(&self) -> Result<Vec<Content>> {
-        Ok(vec![Content::tag(
-            "div",
-            Some(attributes!( "class" => "post-head")),
-            Some(vec![
-                Content::text("published by "),
-                Content::hyperlink(
-                    format!(
-                        "https://medium.com/@{username}",
-                        username = self.creator.username
-                    ),
-                    vec![Content::text(self.creator.name.clone())],
-                    None,
-                ),
-                Content::text(" on medium "),
-                Content::hyperlink(self.medium_url.clone(), vec![Content::text("here")], None),
-                Content::text("."),
-            ]),
-        )])
+/// the first image is usually the largest contentful paint, so it is loaded eagerly
+/// while every other image stays lazy
+fn prioritize_lead_image(body: &mut [Content]) {
+    if let Some(attributes) = first_tag_attributes_mut(body, "img") {
+        attributes.insert("loading".into(), "eager".into());
+        attributes.insert("fetchpriority".into(), "high".into());
     }
 }
 
-const CSS: &str =
-    "body { background-color: #222; color: #ddd; font-family: sans-serif; font-size: 130%; }
-article { width: 60rem; margin: auto }
-img { max-width: 100% }
-pre { background-color: #111; padding: 1rem; border-radius: .5rem; }
-blockquote { background-color: #333; margin: 0; padding: 1rem;  padding-left: 2rem; border-left: 5px solid gray; }
-a { color: cornflowerblue }
-.post-head {  background-color: #333; margin: 0; padding: 1rem; font-size: 80%; }";
+fn first_tag_attributes_mut<'a>(
+    contents: &'a mut [Content],
+    tag_name: &str,
+) -> Option<&'a mut BTreeMap<String, String>> {
+    for content in contents.iter_mut() {
+        if let Content::Tag {
+            name,
+            attributes,
+            children,
+        } = content
+        {
+            if name == tag_name {
+                return Some(attributes);
+            }
+            if let Some(found) = children
+                .as_mut()
+                .and_then(|children| first_tag_attributes_mut(children, tag_name))
+            {
+                return Some(found);
+            }
+        }
+    }
 
-pub struct Page {
-    post: PostResult,
+    None
 }
 
-impl Render for Page {
-    fn render(&self) -> Result<Content> {
-        Ok(Content::tag(
-            "html",
-            None,
-            Some(vec![
-                Content::tag(
-                    "head",
-                    None,
-                    Some(vec![
-                        Content::tag("style", None, Some(vec![Content::text(CSS)])),
-                        Content::tag(
-                            "title",
-                            None,
-                            Some(vec![Content::text(self.post.title.clone())]),
-                        ),
-                    ]),
-                ),
-                Content::tag("body", None, Some(vec![self.post.render()?])),
-            ]),
-        ))
+/// the host of an absolute http(s) url, relative links, fragments and other schemes
+/// don't have one
+fn link_host(href: &str) -> Option<String> {
+    let rest = href
+        .strip_prefix("https://")
+        .or_else(|| href.strip_prefix("http://"))?;
+    let authority = rest.split(['/', '?', '#']).next()?;
+    let host_port = authority.rsplit('@').next()?;
+    let host = match host_port.rsplit_once(':') {
+        Some((host, port)) if port.chars().all(|c| c.is_ascii_digit()) => host,
+        _ => host_port,
+    };
+    let host = host.to_lowercase();
+    let host = host.strip_prefix("www.").unwrap_or(&host);
+
+    (!host.is_empty()).then(|| host.to_string())
+}
+
+fn is_medium_host(host: &str) -> bool {
+    host == "medium.com" || host.ends_with(".medium.com")
+}
+
+fn annotate_link_domains(contents: &mut Vec<Content>) {
+    let mut annotated = Vec::with_capacity(contents.len());
+    for mut content in contents.drain(..) {
+        let mut domain = None;
+        if let Content::Tag {
+            name,
+            attributes,
+            children,
+        } = &mut content
+        {
+            if name == "a" {
+                domain = attributes
+                    .get("href")
+                    .and_then(|href| link_host(href))
+                    .filter(|host| !is_medium_host(host));
+            } else if let Some(children) = children {
+                annotate_link_domains(children);
+            }
+        }
+
+        annotated.push(content);
+        if let Some(domain) = domain {
+            annotated.push(Content::tag_with_class(
+                "span",
+                "link-domain",
+                Some(vec![Content::text(format!(" ({})", domain))]),
+            ));
+        }
     }
+    *contents = annotated;
 }
 
-#[cfg(test)]
-mod test {
-    use crate::client::{Markup, Paragraph};
+/// the letters a word contributes to an abbreviation: its first letter and any
+/// further capitals, e.g. "HT" for "HyperText"
+fn word_initials(word: &str) -> String {
+    let mut letters = word.chars().skip_while(|c| !c.is_alphanumeric());
+    let Some(first) = letters.next() else {
+        return String::new();
+    };
 
-    use super::Render;
+    std::iter::once(first.to_ascii_uppercase())
+        .chain(letters.filter(char::is_ascii_uppercase))
+        .collect()
+}
 
-    #[test]
-    fn test_renders_incorrectly_sorted_markup_correctly() {
-        let p = Paragraph {
-            id: String::from(""),
-            href: None,
-            layout: None,
-            text: Some(String::from("This is a test with some text")),
-            r#type: "P".into(),
-            markups: vec![
-                Markup {
-                    start: 10,
-                    end: 13,
-                    r#type: String::from("EM"),
-                    href: None,
-                },
-                Markup {
+/// the words right before an abbreviation's parentheses that it stands for, as in
+/// "Cascading Style Sheets (CSS)"
+fn abbreviation_expansion(before: &str, abbr: &str) -> Option<String> {
+    if !(2..=6).contains(&abbr.len()) || !abbr.chars().all(|c| c.is_ascii_uppercase()) {
+        return None;
+    }
+
+    let words: Vec<&str> = before.split_whitespace().collect();
+    (1..=abbr.len().min(words.len())).find_map(|count| {
+        let expansion = &words[words.len() - count..];
+        let initials: String = expansion.iter().map(|word| word_initials(word)).collect();
+        (initials == abbr).then(|| unescape_text(&expansion.join(" ")))
+    })
+}
+
+/// splits an escaped text node at every defined abbreviation, `None` when it
+/// defines none
+fn split_abbreviations(text: &str) -> Option<Vec<Content>> {
+    let mut parts = Vec::new();
+    let mut pending = 0;
+    let mut search = 0;
+    while let Some(open) = text[search..].find('(').map(|i| search + i) {
+        let Some(close) = text[open..].find(')').map(|i| open + i) else {
+            break;
+        };
+        let abbr = &text[open + 1..close];
+        search = open + 1;
+        if let Some(expansion) = abbreviation_expansion(&text[pending..open], abbr) {
+            parts.push(Content::Text(text[pending..=open].to_string()));
+            parts.push(Content::tag(
+                "abbr",
+                Some(attributes! {"title" => expansion}),
+                Some(vec![Content::Text(abbr.to_string())]),
+            ));
+            pending = close;
+            search = close;
+        }
+    }
+
+    if parts.is_empty() {
+        return None;
+    }
+    parts.push(Content::Text(text[pending..].to_string()));
+    Some(parts)
+}
+
+/// wraps abbreviations defined in the text, like "Cascading Style Sheets (CSS)",
+/// in `<abbr>` with their expansion as title. code is left alone
+fn mark_abbreviations(contents: &mut Vec<Content>) {
+    let mut marked = Vec::with_capacity(contents.len());
+    for mut content in contents.drain(..) {
+        match &mut content {
+            Content::Text(text) => {
+                if let Some(parts) = split_abbreviations(text) {
+                    marked.extend(parts);
+                    continue;
+                }
+            }
+            Content::Tag { name, children, .. } => {
+                if let Some(children) = children {
+                    if !matches!(name.as_str(), "pre" | "code" | "abbr") {
+                        mark_abbreviations(children);
+                    }
+                }
+            }
+        }
+        marked.push(content);
+    }
+    *contents = marked;
+}
+
+/// collects (abbreviation, expansion) pairs in document order, keeping only the
+/// first expansion of every abbreviation
+fn collect_abbreviations(content: &Content, found: &mut Vec<(String, String)>) {
+    let Content::Tag {
+        name,
+        attributes,
+        children,
+    } = content
+    else {
+        return;
+    };
+
+    if name == "abbr" {
+        let text: String = children
+            .iter()
+            .flatten()
+            .map(|child| child.to_string())
+            .collect();
+        if let Some(title) = attributes.get("title") {
+            if !found.iter().any(|(abbr, _)| *abbr == text) {
+                found.push((text, title.clone()));
+            }
+        }
+    }
+
+    for child in children.iter().flatten() {
+        collect_abbreviations(child, found);
+    }
+}
+
+fn render_glossary(article: &[Content]) -> Option<Content> {
+    let mut abbreviations = Vec::new();
+    for content in article {
+        collect_abbreviations(content, &mut abbreviations);
+    }
+    if abbreviations.is_empty() {
+        return None;
+    }
+
+    let entries = abbreviations
+        .into_iter()
+        .flat_map(|(abbr, title)| {
+            [
+                Content::tag("dt", None, Some(vec![Content::Text(abbr)])),
+                Content::tag("dd", None, Some(vec![Content::text(title)])),
+            ]
+        })
+        .collect();
+
+    Some(Content::tag_with_class(
+        "section",
+        "glossary",
+        Some(vec![
+            Content::tag("h2", None, Some(vec![Content::text("Abbreviations")])),
+            Content::tag("dl", None, Some(entries)),
+        ]),
+    ))
+}
+
+fn heading_level(paragraph: &client::Paragraph) -> Option<usize> {
+    match paragraph.r#type.as_str() {
+        "H1" => Some(1),
+        "H2" => Some(2),
+        "H3" => Some(3),
+        "H4" => Some(4),
+        "H5" => Some(5),
+        "H6" => Some(6),
+        _ => None,
+    }
+}
+
+/// hands out section numbers for a sequence of heading levels. skipped levels don't
+/// produce zeros, a H4 directly below a H2 is simply numbered as its child.
+#[derive(Debug, Default)]
+struct HeadingNumbers {
+    /// (heading level, count) for every currently open section
+    open: Vec<(usize, usize)>,
+}
+
+impl HeadingNumbers {
+    fn next(&mut self, level: usize) -> String {
+        let mut sibling_count = 0;
+        while let Some(&(open_level, count)) = self.open.last() {
+            if open_level <= level {
+                break;
+            }
+            sibling_count = count;
+            self.open.pop();
+        }
+
+        match self.open.last_mut() {
+            Some((open_level, count)) if *open_level == level => *count += 1,
+            _ => self.open.push((level, sibling_count + 1)),
+        }
+
+        self.open
+            .iter()
+            .map(|(_, count)| count.to_string())
+            .collect::<Vec<String>>()
+            .join(".")
+    }
+}
+
+fn number_headings(paragraphs: &[client::Paragraph], rendered: &mut [Content]) {
+    let mut numbers = HeadingNumbers::default();
+    for (paragraph, content) in paragraphs.iter().zip(rendered.iter_mut()) {
+        let Some(level) = heading_level(paragraph) else {
+            continue;
+        };
+        if let Content::Tag {
+            children: Some(children),
+            ..
+        } = content
+        {
+            children.insert(0, Content::text(format!("{} ", numbers.next(level))));
+        }
+    }
+}
+
+fn is_list_item(paragraph: &client::Paragraph) -> bool {
+    matches!(paragraph.r#type.as_str(), "ULI" | "OLI")
+}
+
+/// nesting depth of a list item. medium marks nested items with a `layout` ending in
+/// their depth, like `NESTED_1`, top level items have none
+fn list_level(paragraph: &client::Paragraph) -> usize {
+    paragraph
+        .layout
+        .as_deref()
+        .and_then(|layout| layout.rsplit('_').next())
+        .and_then(|depth| depth.parse().ok())
+        .unwrap_or(0)
+}
+
+/// a <ul> or <ol> that is still collecting items
+struct OpenList {
+    tag: &'static str,
+    level: usize,
+    items: Vec<Content>,
+}
+
+/// closes the innermost open list, nesting it into the last item of its parent list
+fn close_list(open_lists: &mut Vec<OpenList>, grouped: &mut Vec<Content>) {
+    let Some(list) = open_lists.pop() else {
+        return;
+    };
+    let list = Content::tag(list.tag, None, Some(list.items));
+    match open_lists
+        .last_mut()
+        .and_then(|parent| parent.items.last_mut())
+    {
+        Some(Content::Tag {
+            children: Some(children),
+            ..
+        }) => children.push(list),
+        _ => grouped.push(list),
+    }
+}
+
+/// wraps every run of adjacent list items of the same type in a single <ul> or <ol>,
+/// deeper items go into a list inside the item before them
+fn group_list_items(paragraphs: &[client::Paragraph], rendered: Vec<Content>) -> Vec<Content> {
+    let mut grouped = Vec::with_capacity(rendered.len());
+    let mut open_lists: Vec<OpenList> = Vec::new();
+
+    for (paragraph, content) in paragraphs.iter().zip(rendered) {
+        let tag = match paragraph.r#type.as_str() {
+            "ULI" => "ul",
+            "OLI" => "ol",
+            _ => {
+                while !open_lists.is_empty() {
+                    close_list(&mut open_lists, &mut grouped);
+                }
+                grouped.push(content);
+                continue;
+            }
+        };
+
+        // a list can only be one level deeper than its parent
+        let level = match open_lists.last() {
+            Some(list) => list_level(paragraph).min(list.level + 1),
+            None => 0,
+        };
+        while open_lists
+            .last()
+            .is_some_and(|list| list.level > level || (list.level == level && list.tag != tag))
+        {
+            close_list(&mut open_lists, &mut grouped);
+        }
+
+        match open_lists.last_mut() {
+            Some(list) if list.level == level => list.items.push(content),
+            _ => open_lists.push(OpenList {
+                tag,
+                level,
+                items: vec![content],
+            }),
+        }
+    }
+
+    while !open_lists.is_empty() {
+        close_list(&mut open_lists, &mut grouped);
+    }
+
+    grouped
+}
+
+/// cuts the paragraphs down to `limit`, but never in the middle of a list
+fn preview_paragraphs(
+    paragraphs: &[client::Paragraph],
+    limit: Option<usize>,
+) -> &[client::Paragraph] {
+    let Some(limit) = limit else {
+        return paragraphs;
+    };
+
+    let mut end = limit.min(paragraphs.len());
+    if end > 0 && is_list_item(&paragraphs[end - 1]) {
+        let list_type = &paragraphs[end - 1].r#type;
+        while end < paragraphs.len() && &paragraphs[end].r#type == list_type {
+            end += 1;
+        }
+    }
+
+    &paragraphs[..end]
+}
+
+impl client::PostResult {
+    fn render_read_more(&self) -> Content {
+        Content::tag_with_class(
+            "p",
+            "read-more",
+            Some(vec![Content::hyperlink(
+                format!("/{}", self.id),
+                vec![Content::text("read more")],
+                None,
+            )]),
+        )
+    }
+
+    /// links to the medium tag pages of the post, `None` for untagged posts
+    fn render_footer(&self) -> Option<Content> {
+        if self.tags.is_empty() {
+            return None;
+        }
+
+        let tags = self
+            .tags
+            .iter()
+            .map(|tag| {
+                Content::tag(
+                    "li",
+                    None,
+                    Some(vec![Content::hyperlink(
+                        format!("https://medium.com/tag/{}", tag.normalized_tag_slug),
+                        vec![Content::text(tag.display_title.clone())],
+                        None,
+                    )]),
+                )
+            })
+            .collect();
+        Some(Content::tag_with_class("ul", "post-tags", Some(tags)))
+    }
+
+    /// the preview image shown on top of the article, `None` when the post has none
+    fn render_hero(&self, options: &RenderOptions) -> Option<Content> {
+        let image = &self.preview_image;
+        if image.id.is_empty() {
+            return None;
+        }
+
+        let mut attr = attributes! {
+            "class" => "hero",
+            "src" => image_url(&image.id, options),
+            "alt" => "",
+            // the hero is the lead image, see `prioritize_lead_image`
+            "loading" => "eager",
+            "decoding" => "async",
+            "fetchpriority" => "high"
+        };
+        insert_srcset(&mut attr, &image.id, options);
+        if let (Some(width), Some(height)) = (image.original_width, image.original_height) {
+            if width > 0 && height > 0 {
+                attr.insert("width".into(), width.to_string());
+                attr.insert("height".into(), height.to_string());
+            }
+        }
+
+        Some(Content::tag("img", Some(attr), None))
+    }
+
+    fn render_header(&self, options: &RenderOptions) -> Result<Vec<Content>> {
+        let mut header: Vec<Content> = self.render_hero(options).into_iter().collect();
+        let mut head = vec![
+            Content::text("published by "),
+            self.render_author_link(),
+            Content::text(" on medium "),
+            Content::hyperlink(self.medium_url.clone(), vec![Content::text("here")], None),
+            Content::text("."),
+        ];
+        head.extend(self.render_dates());
+        header.push(Content::tag_with_class("div", "post-head", Some(head)));
+        header.extend(self.render_topics());
+        Ok(header)
+    }
+
+    /// the publish date, plus an "updated" note when the post changed on a later day.
+    /// drafts were never published and get no date at all
+    fn render_dates(&self) -> Vec<Content> {
+        let published = self.latest_published_at();
+        if published <= 0 {
+            return vec![];
+        }
+
+        let time = |ms: i64| {
+            Content::tag(
+                "time",
+                Some(attributes!("datetime" => epoch_to_rfc3339(ms))),
+                Some(vec![Content::text(epoch_to_human(ms))]),
+            )
+        };
+        let mut dates = vec![Content::text(" "), time(published)];
+        let updated = self.updated_at();
+        if updated > published && epoch_to_human(updated) != epoch_to_human(published) {
+            dates.push(Content::text(", updated "));
+            dates.push(time(updated));
+        }
+        dates
+    }
+
+    fn render_author_link(&self) -> Content {
+        Content::hyperlink(
+            format!(
+                "https://medium.com/@{username}",
+                username = self.creator.username
+            ),
+            vec![Content::text(self.creator.name.clone())],
+            None,
+        )
+    }
+
+    /// who wrote the post, below the body. the bio is left out when the author has none
+    fn render_author(&self) -> Content {
+        let mut children = vec![Content::tag(
+            "p",
+            None,
+            Some(vec![
+                Content::text("written by "),
+                self.render_author_link(),
+            ]),
+        )];
+        if !self.creator.bio.is_empty() {
+            children.push(Content::tag_with_class(
+                "p",
+                "author-bio",
+                Some(vec![Content::text(self.creator.bio.clone())]),
+            ));
+        }
+
+        Content::tag_with_class("footer", "post-author", Some(children))
+    }
+
+    /// medium's own topics for the post. unlike tags they have no page to link to,
+    /// so they are plain labels. `None` when medium assigned none
+    fn render_topics(&self) -> Option<Content> {
+        if self.topics.is_empty() {
+            return None;
+        }
+
+        let topics = self
+            .topics
+            .iter()
+            .map(|topic| Content::tag("li", None, Some(vec![Content::text(topic.name.clone())])))
+            .collect();
+        Some(Content::tag_with_class("ul", "post-topics", Some(topics)))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::client::{
+        CodeBlockMetadata, IFrame, IFrameMediaResource, Markup, Metadata, Paragraph, PostResult,
+        QueryResponse,
+    };
+    use crate::test_support::CapturingLayer;
+    use std::collections::BTreeMap;
+    use tracing_subscriber::layer::SubscriberExt;
+
+    use super::{Content, Render, RenderOptions};
+
+    fn sample_post() -> PostResult {
+        serde_json::from_str::<QueryResponse>(include_str!("../test/post.json"))
+            .unwrap()
+            .get_post()
+    }
+
+    fn text_paragraph(r#type: &str, text: &str) -> Paragraph {
+        Paragraph {
+            id: String::from(""),
+            href: None,
+            layout: None,
+            text: Some(String::from(text)),
+            r#type: r#type.into(),
+            markups: vec![],
+            metadata: None,
+            iframe: None,
+            code_block_metadata: None,
+            extra: Default::default(),
+        }
+    }
+
+    #[test]
+    fn test_renders_incorrectly_sorted_markup_correctly() {
+        let p = Paragraph {
+            id: String::from(""),
+            href: None,
+            layout: None,
+            text: Some(String::from("This is a test with some text")),
+            r#type: "P".into(),
+            markups: vec![
+                Markup {
+                    start: 10,
+                    end: 13,
+                    r#type: String::from("EM"),
+                    href: None,
+                    user_id: None,
+                },
+                Markup {
                     start: 8,
                     end: 13,
                     r#type: String::from("STRONG"),
                     href: None,
+                    user_id: None,
                 },
             ],
             metadata: None,
             iframe: None,
+            code_block_metadata: None,
+            extra: Default::default(),
         };
 
         assert_eq!(
-            "<p >This is <strong >a <em >test</em></strong> with some text</p>",
+            "<p>This is <strong>a <em>test</em></strong> with some text</p>",
             p.render().unwrap().to_string()
         );
     }
+
+    #[test]
+    fn test_renders_block_quote() {
+        assert_eq!(
+            "<blockquote>quoted text</blockquote>",
+            text_paragraph("BQ", "quoted text")
+                .render()
+                .unwrap()
+                .to_string()
+        );
+    }
+
+    #[test]
+    fn test_renders_pull_quote() {
+        assert_eq!(
+            r#"<blockquote class="pullquote">quoted text</blockquote>"#,
+            text_paragraph("PQ", "quoted text")
+                .render()
+                .unwrap()
+                .to_string()
+        );
+    }
+
+    #[test]
+    fn test_tag_with_class_matches_attributes_macro() {
+        let children = || Some(vec![Content::text("hello")]);
+
+        assert_eq!(
+            Content::tag("div", Some(attributes!("class" => "post-head")), children()).to_string(),
+            Content::tag_with_class("div", "post-head", children()).to_string()
+        );
+    }
+
+    #[test]
+    fn test_preview_renders_limited_paragraphs_and_read_more() {
+        let post = sample_post();
+        let options = RenderOptions {
+            preview_limit: Some(2),
+            ..Default::default()
+        };
+        let html = post.render_with(&options).unwrap().to_string();
+
+        assert!(html.contains("post-head"));
+        assert!(html.contains("<h3>A Sample Post</h3>"));
+        assert!(html.contains("<strong>first</strong> paragraph"));
+        assert!(!html.contains("1*image.png"));
+        assert!(!html.contains("second paragraph"));
+        assert!(html.contains(r#"<p class="read-more"><a href="/a1b2c3d4e5f6">read more</a></p>"#));
+    }
+
+    #[test]
+    fn test_unknown_markup_keeps_plain_text() {
+        let mut paragraph = text_paragraph("P", "highlighted text");
+        paragraph.markups = serde_json::from_str(
+            r#"[{"type": "MARK", "start": 0, "end": 10}, {"type": "EM", "start": 12, "end": 15}]"#,
+        )
+        .unwrap();
+        let html = paragraph.render().unwrap().to_string();
+
+        assert_eq!("<p>highlighted <em>text</em></p>", html);
+    }
+
+    #[test]
+    fn test_unknown_markup_is_logged() {
+        let layer = CapturingLayer::default();
+        let subscriber = tracing_subscriber::registry().with(layer.clone());
+        let _guard = tracing::subscriber::set_default(subscriber);
+
+        let mut paragraph = text_paragraph("P", "highlighted text");
+        paragraph.markups =
+            serde_json::from_str(r#"[{"type": "MARK", "start": 0, "end": 10}]"#).unwrap();
+        paragraph.render().unwrap();
+
+        let fields = layer.fields.lock().unwrap();
+        assert!(
+            fields.contains(&String::from("markup_type=MARK")),
+            "{:?}",
+            fields
+        );
+        assert!(fields.contains(&String::from("message=unknown markup")));
+    }
+
+    #[test]
+    fn test_strikethrough_renders_del() {
+        let mut paragraph = text_paragraph("P", "not this but that");
+        paragraph.markups = serde_json::from_str(
+            r#"[{"type": "STRONG", "start": 0, "end": 7}, {"type": "STRIKE", "start": 0, "end": 7}]"#,
+        )
+        .unwrap();
+        let html = paragraph.render().unwrap().to_string();
+
+        assert_eq!("<p><strong><del>not this</del></strong> but that</p>", html);
+    }
+
+    #[test]
+    fn test_mention_links_to_profile() {
+        let mut paragraph = text_paragraph("P", "thanks Jane Doe!");
+        paragraph.markups = serde_json::from_str(
+            r#"[{"type": "USER", "start": 7, "end": 14, "userId": "abc123"}]"#,
+        )
+        .unwrap();
+        let html = paragraph.render().unwrap().to_string();
+
+        assert_eq!(
+            r#"<p>thanks <a href="https://medium.com/u/abc123">Jane Doe</a>!</p>"#,
+            html
+        );
+    }
+
+    #[test]
+    fn test_subscript_renders_sub() {
+        let mut paragraph = text_paragraph("P", "H2O");
+        paragraph.markups =
+            serde_json::from_str(r#"[{"type": "SUB", "start": 1, "end": 1}]"#).unwrap();
+        let html = paragraph.render().unwrap().to_string();
+
+        assert_eq!("<p>H<sub>2</sub>O</p>", html);
+    }
+
+    #[test]
+    fn test_superscript_renders_sup() {
+        let mut paragraph = text_paragraph("P", "x2 grows");
+        paragraph.markups =
+            serde_json::from_str(r#"[{"type": "SUP", "start": 1, "end": 1}]"#).unwrap();
+        let html = paragraph.render().unwrap().to_string();
+
+        assert_eq!("<p>x<sup>2</sup> grows</p>", html);
+    }
+
+    #[test]
+    fn test_linked_footnote_marker_nests_sup_in_link() {
+        let mut paragraph = text_paragraph("P", "a claim1");
+        paragraph.markups = serde_json::from_str(
+            r##"[{"type": "SUP", "start": 7, "end": 7}, {"type": "A", "start": 7, "end": 7, "href": "#note-1"}]"##,
+        )
+        .unwrap();
+        let html = paragraph.render().unwrap().to_string();
+
+        assert_eq!(
+            r##"<p>a claim<a href="#note-1"><sup>1</sup></a></p>"##,
+            html
+        );
+    }
+
+    #[test]
+    fn test_overlapping_markups_are_split() {
+        let mut paragraph = text_paragraph("P", "This is a test with some text");
+        paragraph.markups = serde_json::from_str(
+            r#"[{"type": "STRONG", "start": 8, "end": 13}, {"type": "EM", "start": 10, "end": 16}]"#,
+        )
+        .unwrap();
+        let html = paragraph.render().unwrap().to_string();
+
+        assert_eq!(
+            "<p>This is <strong>a </strong><em><strong>test</strong> wi</em>th some text</p>",
+            html
+        );
+    }
+
+    #[test]
+    fn test_empty_text_renders_nothing() {
+        let markups: Vec<Markup> =
+            serde_json::from_str(r#"[{"type": "STRONG", "start": 0, "end": 0}]"#).unwrap();
+
+        for markups in [&[][..], &markups[..]] {
+            let html: String = super::render_text("", markups)
+                .unwrap()
+                .iter()
+                .map(Content::to_string)
+                .collect();
+            assert_eq!("", html);
+        }
+    }
+
+    #[test]
+    fn test_markup_past_the_text_is_ignored() {
+        let mut paragraph = text_paragraph("P", "short");
+        paragraph.markups = serde_json::from_str(
+            r#"[{"type": "STRONG", "start": 40, "end": 45}, {"type": "EM", "start": 3, "end": 20}]"#,
+        )
+        .unwrap();
+        let html = paragraph.render().unwrap().to_string();
+
+        assert_eq!("<p>sho<em>rt</em></p>", html);
+    }
+
+    #[test]
+    fn test_markup_on_empty_text_is_ignored() {
+        let mut paragraph = text_paragraph("P", "");
+        paragraph.markups =
+            serde_json::from_str(r#"[{"type": "STRONG", "start": 0, "end": 0}]"#).unwrap();
+
+        assert_eq!("<p></p>", paragraph.render().unwrap().to_string());
+    }
+
+    #[test]
+    fn test_newlines_become_line_breaks() {
+        let mut paragraph = text_paragraph("P", "first line\nsecond line");
+        paragraph.markups =
+            serde_json::from_str(r#"[{"type": "EM", "start": 11, "end": 16}]"#).unwrap();
+        let html = paragraph.render().unwrap().to_string();
+
+        assert_eq!("<p>first line<br/><em>second</em> line</p>", html);
+    }
+
+    #[test]
+    fn test_preformatted_keeps_newlines() {
+        let html = text_paragraph("PRE", "fn main() {\n}")
+            .render()
+            .unwrap()
+            .to_string();
+        assert_eq!("<pre><code>fn main() {\n}</code></pre>", html);
+    }
+
+    #[test]
+    fn test_plain_text_puts_blocks_on_lines() {
+        let image = Content::tag(
+            "img",
+            Some(attributes! {"src" => "cat.png", "alt" => "a cat"}),
+            None,
+        );
+        let article = Content::tag(
+            "article",
+            None,
+            Some(vec![
+                Content::tag("h1", None, Some(vec![Content::text("Title")])),
+                Content::tag(
+                    "p",
+                    None,
+                    Some(vec![
+                        Content::text("some "),
+                        Content::tag("strong", None, Some(vec![Content::text("bold")])),
+                        Content::text(" text & more"),
+                    ]),
+                ),
+                Content::tag("figure", None, Some(vec![image])),
+                Content::tag(
+                    "ul",
+                    None,
+                    Some(vec![
+                        Content::tag("li", None, Some(vec![Content::text("one")])),
+                        Content::tag("li", None, Some(vec![Content::text("two")])),
+                    ]),
+                ),
+            ]),
+        );
+
+        assert_eq!(
+            "Title\nsome bold text & more\na cat\none\ntwo",
+            article.to_plain_text()
+        );
+    }
+
+    #[test]
+    fn test_plain_text_of_rendered_post() {
+        let text = sample_post().render().unwrap().to_plain_text();
+        assert!(text.contains("\nA Sample Post\nThis is the first paragraph.\n"));
+    }
+
+    #[test]
+    fn test_tag_without_attributes_has_no_spaces() {
+        assert_eq!("<br/>", Content::tag("br", None, None).to_string());
+        assert_eq!(
+            "<p>text</p>",
+            Content::tag("p", None, Some(vec![Content::text("text")])).to_string()
+        );
+    }
+
+    #[test]
+    fn test_attributes_have_no_trailing_space() {
+        let image = Content::tag("img", Some(attributes! {"src" => "cat.png"}), None);
+        assert_eq!(r#"<img src="cat.png"/>"#, image.to_string());
+
+        let link = Content::hyperlink("/a1b2c3d4e5f6", vec![Content::text("more")], None);
+        assert_eq!(r#"<a href="/a1b2c3d4e5f6">more</a>"#, link.to_string());
+    }
+
+    #[test]
+    fn test_attributes_serialize_sorted() {
+        let image = Content::tag(
+            "img",
+            Some(attributes! {"src" => "cat.png", "alt" => "a cat", "loading" => "lazy"}),
+            None,
+        );
+        assert_eq!(
+            r#"<img alt="a cat" loading="lazy" src="cat.png"/>"#,
+            image.to_string()
+        );
+    }
+
+    #[test]
+    fn test_write_to_matches_to_string() {
+        let content = sample_post().render().unwrap();
+        let mut out = Vec::new();
+        content.write_to(&mut out).unwrap();
+        assert_eq!(content.to_string(), String::from_utf8(out).unwrap());
+
+        let image = Content::tag(
+            "img",
+            Some(BTreeMap::from([(
+                String::from("alt"),
+                String::from("\"quoted\""),
+            )])),
+            None,
+        );
+        let mut out = Vec::new();
+        image.write_to(&mut out).unwrap();
+        assert_eq!(
+            r#"<img alt="&quot;quoted&quot;"/>"#,
+            String::from_utf8(out).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_display_matches_serialization() {
+        let content = Content::tag(
+            "p",
+            Some(BTreeMap::from([(
+                String::from("class"),
+                String::from("lead"),
+            )])),
+            Some(vec![Content::text("a < b"), Content::tag("br", None, None)]),
+        );
+
+        assert_eq!(
+            r#"<p class="lead">a &lt; b<br/></p>"#,
+            format!("{}", content)
+        );
+    }
+
+    #[test]
+    fn test_pretty_string_indents_blocks() {
+        let article = Content::tag(
+            "article",
+            None,
+            Some(vec![
+                Content::tag(
+                    "p",
+                    None,
+                    Some(vec![
+                        Content::text("some "),
+                        Content::tag("em", None, Some(vec![Content::text("inline")])),
+                        Content::text(" text"),
+                    ]),
+                ),
+                Content::tag(
+                    "ul",
+                    None,
+                    Some(vec![Content::tag(
+                        "li",
+                        None,
+                        Some(vec![Content::text("item")]),
+                    )]),
+                ),
+                Content::tag("pre", None, Some(vec![Content::text("fn main() {\n}")])),
+            ]),
+        );
+
+        assert_eq!(
+            concat!(
+                "<article>\n",
+                "  <p>some <em>inline</em> text</p>\n",
+                "  <ul>\n",
+                "    <li>item</li>\n",
+                "  </ul>\n",
+                "  <pre>fn main() {\n}</pre>\n",
+                "</article>\n"
+            ),
+            article.to_pretty_string(2)
+        );
+    }
+
+    #[test]
+    fn test_divider_renders_hr() {
+        let html = text_paragraph("HR", "").render().unwrap().to_string();
+        assert_eq!("<hr/>", html);
+    }
+
+    #[test]
+    fn test_preview_image_renders_as_hero() {
+        let html = sample_post().render().unwrap().to_string();
+
+        let hero = html
+            .strip_prefix("<article><img")
+            .and_then(|rest| rest.split_once("/>"))
+            .unwrap()
+            .0;
+        assert!(hero.contains(r#" class="hero""#));
+        assert!(hero.contains(r#" src="https://miro.medium.com/v2/resize:fit:2000/1*preview.png""#));
+        assert!(hero.contains(r#" width="1600""#));
+        assert!(hero.contains(r#" height="900""#));
+        assert!(hero.contains(r#" loading="eager""#));
+        assert!(hero.contains(r#" fetchpriority="high""#));
+    }
+
+    #[test]
+    fn test_body_images_stay_lazy_below_a_hero() {
+        let html = sample_post().render().unwrap().to_string();
+        let (hero, body) = html.split_once("/>").unwrap();
+
+        assert!(hero.contains(r#" class="hero""#));
+
+        assert!(body.contains("1*image.png"));
+        assert!(!body.contains(r#" loading="eager""#));
+        assert!(!body.contains(r#" fetchpriority="high""#));
+        assert_eq!(1, html.matches(r#" fetchpriority="high""#).count());
+    }
+
+    #[test]
+    fn test_post_without_preview_image_has_no_hero() {
+        let mut post = sample_post();
+        post.preview_image.id.clear();
+
+        assert!(!post.render().unwrap().to_string().contains("hero"));
+    }
+
+    #[test]
+    fn test_tags_are_linked_in_footer() {
+        let mut post = sample_post();
+        post.tags.push(
+            serde_json::from_str(
+                r#"{"id": "web", "displayTitle": "Web Development", "normalizedTagSlug": "web-development"}"#,
+            )
+            .unwrap(),
+        );
+        let html = post.render().unwrap().to_string();
+
+        assert!(html.contains(concat!(
+            r#"<ul class="post-tags">"#,
+            r#"<li><a href="https://medium.com/tag/rust">Rust</a></li>"#,
+            r#"<li><a href="https://medium.com/tag/web-development">Web Development</a></li>"#,
+            "</ul></article>"
+        )));
+    }
+
+    #[test]
+    fn test_topics_render_as_labels() {
+        let mut post = sample_post();
+        post.topics
+            .push(serde_json::from_str(r#"{"topicId": "t2", "name": "Data & Science"}"#).unwrap());
+        let html = post.render().unwrap().to_string();
+
+        assert!(html.contains(concat!(
+            r#"<ul class="post-topics">"#,
+            "<li>Programming</li>",
+            "<li>Data &amp; Science</li>",
+            "</ul>"
+        )));
+    }
+
+    #[test]
+    fn test_post_without_topics_has_no_topic_list() {
+        let mut post = sample_post();
+        post.topics.clear();
+
+        assert!(!post.render().unwrap().to_string().contains("post-topics"));
+    }
+
+    #[test]
+    fn test_author_bio_is_shown_below_the_post() {
+        let mut post = sample_post();
+        post.creator.bio = String::from("Writes about <Rust> & more");
+        let html = post.render().unwrap().to_string();
+
+        assert!(html.contains(concat!(
+            r#"<footer class="post-author"><p>written by <a href="https://medium.com/@jdoe">Jane Doe</a></p>"#,
+            r#"<p class="author-bio">Writes about &lt;Rust&gt; &amp; more</p></footer>"#
+        )));
+    }
+
+    #[test]
+    fn test_empty_bio_only_links_the_author() {
+        let mut post = sample_post();
+        post.creator.bio.clear();
+        let html = post.render().unwrap().to_string();
+
+        assert!(html.contains(concat!(
+            r#"<footer class="post-author"><p>written by <a href="https://medium.com/@jdoe">Jane Doe</a></p>"#,
+            "</footer>"
+        )));
+        assert!(!html.contains("author-bio"));
+    }
+
+    #[test]
+    fn test_header_shows_publish_and_update_dates() {
+        let html = sample_post().render().unwrap().to_string();
+
+        assert!(html.contains(concat!(
+            r#" <time datetime="2020-05-15T06:01:09Z">May 15, 2020</time>"#,
+            r#", updated <time datetime="2020-05-16T06:01:09Z">May 16, 2020</time></div>"#
+        )));
+    }
+
+    #[test]
+    fn test_same_day_update_is_not_shown() {
+        let mut json: serde_json::Value =
+            serde_json::from_str(include_str!("../test/post.json")).unwrap();
+        json["data"]["postResult"]["updatedAt"] = serde_json::Value::from(1589526069693u64);
+        let post = serde_json::from_value::<QueryResponse>(json)
+            .unwrap()
+            .get_post();
+        let html = post.render().unwrap().to_string();
+
+        assert!(html.contains(r#"<time datetime="2020-05-15T06:01:09Z">May 15, 2020</time></div>"#));
+        assert!(!html.contains("updated"));
+    }
+
+    #[test]
+    fn test_untagged_post_has_no_footer() {
+        let mut post = sample_post();
+        post.tags.clear();
+
+        assert!(!post.render().unwrap().to_string().contains("post-tags"));
+    }
+
+    #[test]
+    fn test_preview_without_limit_renders_everything() {
+        let html = sample_post().render().unwrap().to_string();
+
+        assert!(html.contains("second paragraph"));
+        assert!(!html.contains("read-more"));
+    }
+
+    #[test]
+    fn test_preview_does_not_cut_lists() {
+        let paragraphs = vec![
+            text_paragraph("P", "intro"),
+            text_paragraph("ULI", "one"),
+            text_paragraph("ULI", "two"),
+            text_paragraph("P", "outro"),
+        ];
+
+        assert_eq!(3, super::preview_paragraphs(&paragraphs, Some(2)).len());
+        assert_eq!(1, super::preview_paragraphs(&paragraphs, Some(1)).len());
+    }
+
+    #[test]
+    fn test_pre_preserves_whitespace() {
+        let code = "fn main() {\n    if x < 1 {\n\treturn;\n    }\n}";
+        let p = text_paragraph("PRE", code);
+
+        assert_eq!(
+            "<pre><code>fn main() {\n    if x &lt; 1 {\n\treturn;\n    }\n}</code></pre>",
+            p.render().unwrap().to_string()
+        );
+    }
+
+    #[test]
+    fn test_pre_marks_code_language() {
+        let mut p = text_paragraph("PRE", "let x = 1;");
+        p.code_block_metadata = Some(CodeBlockMetadata {
+            lang: Some(String::from("Rust")),
+        });
+
+        assert_eq!(
+            r#"<pre><code class="language-rust">let x = 1;</code></pre>"#,
+            p.render().unwrap().to_string()
+        );
+    }
+
+    #[test]
+    fn test_pre_expands_tabs() {
+        let p = text_paragraph("PRE", "a\n\tb\n\t\tc");
+        let options = RenderOptions {
+            tab_width: Some(2),
+            ..Default::default()
+        };
+
+        assert_eq!(
+            "<pre><code>a\n  b\n    c</code></pre>",
+            p.render_with(&options).unwrap().to_string()
+        );
+    }
+
+    #[test]
+    fn test_image_proxy_uses_stable_path() {
+        let p = image_paragraph("1*abc.png");
+        let options = RenderOptions {
+            image_proxy: true,
+            ..Default::default()
+        };
+
+        let html = p.render_with(&options).unwrap().to_string();
+        assert!(html.contains(r#" src="/img/2000/1*abc.png""#));
+
+        let html = p.render().unwrap().to_string();
+        assert!(html.contains(r#" src="https://miro.medium.com/v2/resize:fit:2000/1*abc.png""#));
+    }
+
+    #[cfg(not(feature = "math"))]
+    #[test]
+    fn test_math_delimiters_are_plain_text_without_math_feature() {
+        assert_eq!(
+            "<p>so $a^2$ holds</p>",
+            text_paragraph("P", "so $a^2$ holds")
+                .render()
+                .unwrap()
+                .to_string()
+        );
+    }
+
+    #[test]
+    fn test_heading_numbers_with_skipped_level() {
+        let mut numbers = super::HeadingNumbers::default();
+        let sequence: Vec<String> = [1, 2, 2, 4, 2, 1, 3]
+            .iter()
+            .map(|level| numbers.next(*level))
+            .collect();
+
+        assert_eq!(
+            vec!["1", "1.1", "1.2", "1.2.1", "1.3", "2", "2.1"],
+            sequence
+        );
+    }
+
+    #[test]
+    fn test_numbered_headings_are_prefixed() {
+        let paragraphs = vec![
+            text_paragraph("H3", "Intro"),
+            text_paragraph("P", "text"),
+            text_paragraph("H4", "Detail"),
+        ];
+        let mut rendered: Vec<Content> = paragraphs.iter().map(|p| p.render().unwrap()).collect();
+        super::number_headings(&paragraphs, &mut rendered);
+
+        assert_eq!("<h3>1 Intro</h3>", rendered[0].to_string());
+        assert_eq!("<p>text</p>", rendered[1].to_string());
+        assert_eq!("<h4>1.1 Detail</h4>", rendered[2].to_string());
+    }
+
+    fn iframe_paragraph(src: &str) -> Paragraph {
+        let mut p = text_paragraph("IFRAME", "");
+        p.iframe = Some(IFrame {
+            media_resource: IFrameMediaResource {
+                id: String::from("embed"),
+                iframe_src: String::from(src),
+                iframe_height: 0,
+                iframe_width: 0,
+                title: String::from("a tweet"),
+            },
+        });
+        p
+    }
+
+    #[test]
+    fn test_tweet_renders_as_widget_blockquote() {
+        let p = iframe_paragraph("https://cdn.embedly.com/widgets/media.html?url=https%3A%2F%2Ftwitter.com%2Fjdoe%2Fstatus%2F12345&type=text%2Fhtml");
+        let options = RenderOptions {
+            twitter_widget: true,
+            ..Default::default()
+        };
+
+        let rendered = p.render_with(&options).unwrap();
+        let html = rendered.to_string();
+        assert!(html.starts_with(r#"<blockquote class="twitter-tweet"><a "#));
+        assert!(html.contains(r#" href="https://twitter.com/jdoe/status/12345""#));
+        assert!(html.ends_with(">a tweet</a></blockquote>"));
+        assert!(rendered.contains_class("twitter-tweet"));
+    }
+
+    #[test]
+    fn test_tweet_renders_as_link_without_widget() {
+        let p = iframe_paragraph("https://x.com/jdoe/status/12345");
+        let html = p.render().unwrap().to_string();
+
+        assert!(html.starts_with(r#"<p class="tweet">Tweet: <a "#));
+        assert!(html.contains(r#" href="https://x.com/jdoe/status/12345""#));
+        assert!(html.ends_with(">a tweet</a></p>"));
+    }
+
+    #[test]
+    fn test_youtube_renders_embedded_player() {
+        let mut p = iframe_paragraph("https://cdn.embedly.com/widgets/media.html?src=https%3A%2F%2Fwww.youtube.com%2Fembed%2FdQw4w9WgXcQ%3Ffeature%3Doembed&url=http%3A%2F%2Fwww.youtube.com%2Fwatch%3Fv%3DdQw4w9WgXcQ&type=text%2Fhtml");
+        if let Some(iframe) = p.iframe.as_mut() {
+            iframe.media_resource.iframe_width = 854;
+            iframe.media_resource.iframe_height = 480;
+        }
+        let html = p.render().unwrap().to_string();
+
+        assert!(html.starts_with(r#"<div class="embed"><iframe "#));
+        assert!(html.contains(r#" src="https://www.youtube.com/embed/dQw4w9WgXcQ?feature=oembed""#));
+        assert!(html.contains(r#" allowfullscreen="""#));
+        assert!(html.contains(r#" width="854""#));
+        assert!(html.contains(r#" height="480""#));
+        assert!(html.contains(r#" style="aspect-ratio: 854 / 480""#));
+        assert!(html.ends_with("></iframe></div>"));
+    }
+
+    #[test]
+    fn test_embed_without_dimensions_defaults_to_16_9() {
+        let p = iframe_paragraph("https://player.vimeo.com/video/76979871");
+        let html = p.render().unwrap().to_string();
+
+        assert!(html.contains(r#" src="https://player.vimeo.com/video/76979871""#));
+        assert!(html.contains(r#" width="640""#));
+        assert!(html.contains(r#" height="360""#));
+        assert!(html.contains(r#" style="aspect-ratio: 640 / 360""#));
+    }
+
+    #[test]
+    fn test_unknown_iframe_link_carries_dimensions() {
+        let mut p = iframe_paragraph("https://example.com/embed");
+        if let Some(iframe) = p.iframe.as_mut() {
+            iframe.media_resource.iframe_width = 400;
+            iframe.media_resource.iframe_height = 300;
+        }
+        let html = p.render().unwrap().to_string();
+
+        assert!(html.contains(r#" data-width="400""#));
+        assert!(html.contains(r#" data-height="300""#));
+    }
+
+    #[test]
+    fn test_unknown_iframe_renders_link() {
+        let p = iframe_paragraph("https://example.com/embed");
+        let html = p.render().unwrap().to_string();
+
+        assert!(html.starts_with("<a "));
+        assert!(html.contains(r#" href="https://example.com/embed""#));
+        assert!(html.contains(r#" target="_blank""#));
+        assert!(html.contains(r#" rel="noopener noreferrer""#));
+        assert!(html.ends_with(">IFRAME: a tweet</a>"));
+    }
+
+    #[test]
+    fn test_collect_links_finds_anchors_and_iframe_links() {
+        let article = Content::tag(
+            "article",
+            None,
+            Some(vec![
+                Content::tag(
+                    "p",
+                    None,
+                    Some(vec![
+                        Content::hyperlink("https://example.com/a", vec![Content::text("a")], None),
+                        Content::text(" and "),
+                        Content::hyperlink("/a1b2c3d4e5f6", vec![Content::text("b")], None),
+                    ]),
+                ),
+                iframe_paragraph("https://example.com/embed")
+                    .render()
+                    .unwrap(),
+            ]),
+        );
+
+        assert_eq!(
+            vec![
+                "https://example.com/a",
+                "/a1b2c3d4e5f6",
+                "https://example.com/embed"
+            ],
+            article.collect_links()
+        );
+        assert!(Content::text("<a href=\"x\">").collect_links().is_empty());
+    }
+
+    fn image_paragraph(id: &str) -> Paragraph {
+        let mut p = text_paragraph("IMG", "");
+        p.metadata = Some(Metadata {
+            alt: None,
+            id: String::from(id),
+            original_width: Some(800),
+            original_height: Some(600),
+        });
+        p
+    }
+
+    #[test]
+    fn test_image_caption_renders_figcaption() {
+        let mut paragraph = image_paragraph("1*image.png");
+        paragraph.text = Some(String::from("Photo by Jane"));
+        paragraph.markups =
+            serde_json::from_str(r#"[{"type": "STRONG", "start": 9, "end": 12}]"#).unwrap();
+        let html = paragraph.render().unwrap().to_string();
+
+        assert!(html.starts_with("<figure><img "));
+        assert!(html.ends_with("<figcaption>Photo by <strong>Jane</strong></figcaption></figure>"));
+    }
+
+    #[test]
+    fn test_image_without_caption_renders_plain_figure() {
+        let html = image_paragraph("1*image.png").render().unwrap().to_string();
+
+        assert!(html.starts_with("<figure><img "));
+        assert!(html.ends_with("/></figure>"));
+        assert!(!html.contains("figcaption"));
+    }
+
+    #[test]
+    fn test_lead_image_gets_high_priority() {
+        let paragraphs = [
+            text_paragraph("P", "intro"),
+            image_paragraph("lead.png"),
+            image_paragraph("second.png"),
+        ];
+        let mut rendered: Vec<Content> = paragraphs.iter().map(|p| p.render().unwrap()).collect();
+        super::prioritize_lead_image(&mut rendered);
+
+        let lead = rendered[1].to_string();
+        assert!(lead.contains(r#" loading="eager""#));
+        assert!(lead.contains(r#" fetchpriority="high""#));
+        assert!(lead.contains(r#" decoding="async""#));
+
+        let second = rendered[2].to_string();
+        assert!(second.contains(r#" loading="lazy""#));
+        assert!(second.contains(r#" fetchpriority="low""#));
+    }
+
+    fn render_grouped(paragraphs: &[Paragraph]) -> String {
+        let rendered = paragraphs.iter().map(|p| p.render().unwrap()).collect();
+        super::group_list_items(paragraphs, rendered)
+            .iter()
+            .map(|c| c.to_string())
+            .collect()
+    }
+
+    #[test]
+    fn test_groups_list_items() {
+        let paragraphs = [
+            text_paragraph("ULI", "one"),
+            text_paragraph("ULI", "two"),
+            text_paragraph("ULI", "three"),
+        ];
+
+        assert_eq!(
+            "<ul><li>one</li><li>two</li><li>three</li></ul>",
+            render_grouped(&paragraphs)
+        );
+    }
+
+    #[test]
+    fn test_groups_lists_between_paragraphs() {
+        let paragraphs = [
+            text_paragraph("OLI", "first"),
+            text_paragraph("P", "text"),
+            text_paragraph("ULI", "last"),
+        ];
+
+        assert_eq!(
+            "<ol><li>first</li></ol><p>text</p><ul><li>last</li></ul>",
+            render_grouped(&paragraphs)
+        );
+    }
+
+    #[test]
+    fn test_interleaved_list_types_start_new_lists() {
+        let paragraphs = [
+            text_paragraph("ULI", "a"),
+            text_paragraph("OLI", "b"),
+            text_paragraph("OLI", "c"),
+            text_paragraph("ULI", "d"),
+        ];
+
+        assert_eq!(
+            "<ul><li>a</li></ul><ol><li>b</li><li>c</li></ol><ul><li>d</li></ul>",
+            render_grouped(&paragraphs)
+        );
+    }
+
+    fn nested_item(r#type: &str, text: &str, depth: usize) -> Paragraph {
+        let mut paragraph = text_paragraph(r#type, text);
+        paragraph.layout = Some(format!("NESTED_{}", depth));
+        paragraph
+    }
+
+    #[test]
+    fn test_nested_items_render_inside_their_parent_item() {
+        let paragraphs = [
+            text_paragraph("ULI", "a"),
+            nested_item("OLI", "a1", 1),
+            nested_item("OLI", "a2", 1),
+            nested_item("ULI", "a2x", 2),
+            text_paragraph("ULI", "b"),
+            text_paragraph("P", "text"),
+        ];
+
+        assert_eq!(
+            concat!(
+                "<ul><li>a<ol><li>a1</li><li>a2<ul><li>a2x</li></ul></li></ol></li>",
+                "<li>b</li></ul><p>text</p>"
+            ),
+            render_grouped(&paragraphs)
+        );
+    }
+
+    #[test]
+    fn test_nesting_without_parent_starts_at_top_level() {
+        let paragraphs = [nested_item("ULI", "deep", 2), text_paragraph("ULI", "top")];
+
+        assert_eq!(
+            "<ul><li>deep</li><li>top</li></ul>",
+            render_grouped(&paragraphs)
+        );
+    }
+
+    #[test]
+    fn test_fallback_image_is_set_as_onerror() {
+        let p = image_paragraph("stale.png");
+        let options = RenderOptions {
+            fallback_image: Some(String::from("/missing.svg?it's=\"bad\"")),
+            ..Default::default()
+        };
+
+        let html = p.render_with(&options).unwrap().to_string();
+        assert!(html
+            .contains(r#" onerror="this.onerror=null;this.src='/missing.svg?it%27s=%22bad%22';""#));
+        assert!(!p.render().unwrap().to_string().contains("onerror"));
+    }
+
+    #[test]
+    fn test_renders_code_markup() {
+        let mut p = text_paragraph("P", "call foo() now");
+        p.markups = vec![Markup {
+            start: 5,
+            end: 9,
+            r#type: String::from("CODE"),
+            href: None,
+            user_id: None,
+        }];
+
+        assert_eq!(
+            "<p>call <code>foo()</code> now</p>",
+            p.render().unwrap().to_string()
+        );
+    }
+
+    #[test]
+    fn test_renders_code_nested_in_strong() {
+        let mut p = text_paragraph("P", "call foo() now");
+        p.markups = vec![
+            Markup {
+                start: 5,
+                end: 9,
+                r#type: String::from("CODE"),
+                href: None,
+                user_id: None,
+            },
+            Markup {
+                start: 0,
+                end: 9,
+                r#type: String::from("STRONG"),
+                href: None,
+                user_id: None,
+            },
+        ];
+
+        assert_eq!(
+            "<p><strong>call <code>foo()</code></strong> now</p>",
+            p.render().unwrap().to_string()
+        );
+    }
+
+    fn abbr(text: &str, title: &str) -> Content {
+        Content::tag(
+            "abbr",
+            Some(attributes!("title" => title)),
+            Some(vec![Content::text(text)]),
+        )
+    }
+
+    #[test]
+    fn test_glossary_lists_distinct_abbreviations() {
+        let article = vec![
+            Content::tag(
+                "p",
+                None,
+                Some(vec![
+                    abbr("HTML", "HyperText Markup Language"),
+                    Content::text(" and "),
+                    abbr("CSS", "Cascading Style Sheets"),
+                ]),
+            ),
+            Content::tag(
+                "p",
+                None,
+                Some(vec![abbr("HTML", "HyperText Markup Language")]),
+            ),
+        ];
+
+        assert_eq!(
+            "<section class=\"glossary\"><h2>Abbreviations</h2><dl><dt>HTML</dt><dd>HyperText Markup Language</dd><dt>CSS</dt><dd>Cascading Style Sheets</dd></dl></section>",
+            super::render_glossary(&article).unwrap().to_string()
+        );
+    }
+
+    #[test]
+    fn test_defined_abbreviations_are_marked() {
+        let html = text_paragraph(
+            "P",
+            "Cascading Style Sheets (CSS) style HyperText Markup Language (HTML), not (OK) or (USA)",
+        )
+        .render()
+        .map(|p| {
+            let mut body = vec![p];
+            super::mark_abbreviations(&mut body);
+            body[0].to_string()
+        })
+        .unwrap();
+
+        assert_eq!(
+            concat!(
+                r#"<p>Cascading Style Sheets (<abbr title="Cascading Style Sheets">CSS</abbr>) "#,
+                r#"style HyperText Markup Language (<abbr title="HyperText Markup Language">HTML</abbr>), "#,
+                "not (OK) or (USA)</p>"
+            ),
+            html
+        );
+    }
+
+    #[test]
+    fn test_code_is_not_marked() {
+        let mut body = vec![text_paragraph("PRE", "Cascading Style Sheets (CSS)")
+            .render()
+            .unwrap()];
+        super::mark_abbreviations(&mut body);
+
+        assert!(!body[0].to_string().contains("<abbr"));
+    }
+
+    #[test]
+    fn test_post_glossary_lists_abbreviations_from_text() {
+        let paragraphs = vec![
+            text_paragraph("P", "We use Cascading Style Sheets (CSS) for looks"),
+            text_paragraph(
+                "P",
+                "and application programming interfaces (API) for data.",
+            ),
+            text_paragraph("P", "Again, Cascading Style Sheets (CSS)."),
+        ];
+        let mut json = serde_json::to_value(sample_post()).unwrap();
+        json["content"]["bodyModel"]["paragraphs"] = serde_json::to_value(&paragraphs).unwrap();
+        let post: PostResult = serde_json::from_value(json).unwrap();
+        let options = RenderOptions {
+            abbreviation_glossary: true,
+            ..Default::default()
+        };
+
+        let html = post.render_with(&options).unwrap().to_string();
+        assert!(html.contains(concat!(
+            "<dl><dt>CSS</dt><dd>Cascading Style Sheets</dd>",
+            "<dt>API</dt><dd>application programming interfaces</dd></dl>"
+        )));
+    }
+
+    #[test]
+    fn test_abbreviations_are_not_marked_by_default() {
+        let mut json = serde_json::to_value(sample_post()).unwrap();
+        json["content"]["bodyModel"]["paragraphs"] = serde_json::to_value(vec![text_paragraph(
+            "P",
+            "We use Cascading Style Sheets (CSS) for looks",
+        )])
+        .unwrap();
+        let post: PostResult = serde_json::from_value(json).unwrap();
+
+        let html = post.render().unwrap().to_string();
+        assert!(!html.contains("<abbr"));
+        assert!(html.contains("Cascading Style Sheets (CSS)"));
+    }
+
+    #[test]
+    fn test_no_glossary_without_abbreviations() {
+        let article = vec![text_paragraph("P", "plain").render().unwrap()];
+
+        assert!(super::render_glossary(&article).is_none());
+    }
+
+    #[test]
+    fn test_image_alt_is_escaped() {
+        let mut p = image_paragraph("1*abc.png");
+        p.metadata.as_mut().unwrap().alt = Some(String::from(r#"a "quoted" <title>"#));
+
+        let html = p.render().unwrap().to_string();
+        assert!(html.contains(r#" alt="a &quot;quoted&quot; &lt;title&gt;""#));
+    }
+
+    #[test]
+    fn test_missing_alt_is_empty() {
+        let html = image_paragraph("1*abc.png").render().unwrap().to_string();
+
+        assert!(html.contains(r#" alt="""#));
+    }
+
+    #[test]
+    fn test_image_dimensions_are_emitted() {
+        let mut p = image_paragraph("1*abc.png");
+        let metadata = p.metadata.as_mut().unwrap();
+        metadata.original_width = Some(1600);
+        metadata.original_height = Some(900);
+
+        let html = p.render().unwrap().to_string();
+        assert!(html.contains(r#" width="1600""#));
+        assert!(html.contains(r#" height="900""#));
+    }
+
+    #[test]
+    fn test_partial_image_dimensions_are_skipped() {
+        let mut p = image_paragraph("1*abc.png");
+        p.metadata.as_mut().unwrap().original_height = None;
+
+        let html = p.render().unwrap().to_string();
+        assert!(!html.contains("width="));
+        assert!(!html.contains("height="));
+    }
+
+    #[test]
+    fn test_image_width_is_configurable() {
+        let options = RenderOptions {
+            image_width: Some(800),
+            ..Default::default()
+        };
+
+        let html = image_paragraph("1*abc.png")
+            .render_with(&options)
+            .unwrap()
+            .to_string();
+        assert!(html.contains(r#" src="https://miro.medium.com/v2/resize:fit:800/1*abc.png""#));
+    }
+
+    #[test]
+    fn test_images_offer_several_widths() {
+        let html = image_paragraph("1*abc.png").render().unwrap().to_string();
+
+        assert!(html.contains(concat!(
+            r#" srcset="https://miro.medium.com/v2/resize:fit:640/1*abc.png 640w, "#,
+            r#"https://miro.medium.com/v2/resize:fit:1000/1*abc.png 1000w, "#,
+            r#"https://miro.medium.com/v2/resize:fit:2000/1*abc.png 2000w""#
+        )));
+        assert!(html.contains(r#" sizes="(max-width: 60rem) 100vw, 60rem""#));
+        assert!(html.contains(r#" src="https://miro.medium.com/v2/resize:fit:2000/1*abc.png""#));
+    }
+
+    #[test]
+    fn test_proxied_srcset_requests_each_width() {
+        let options = RenderOptions {
+            image_proxy: true,
+            ..Default::default()
+        };
+
+        let html = image_paragraph("1*abc.png")
+            .render_with(&options)
+            .unwrap()
+            .to_string();
+        assert!(html.contains(
+            r#" srcset="/img/640/1*abc.png 640w, /img/1000/1*abc.png 1000w, /img/2000/1*abc.png 2000w""#
+        ));
+        assert!(html.contains(r#" sizes="(max-width: 60rem) 100vw, 60rem""#));
+    }
+
+    #[test]
+    fn test_proxied_images_carry_their_width() {
+        let options = RenderOptions {
+            image_proxy: true,
+            image_width: Some(800),
+            ..Default::default()
+        };
+
+        let html = image_paragraph("1*abc.png")
+            .render_with(&options)
+            .unwrap()
+            .to_string();
+        assert!(html.contains(r#" src="/img/800/1*abc.png""#));
+        assert_eq!(
+            (800, "1*abc.png"),
+            super::parse_proxied_image("800/1*abc.png")
+        );
+    }
+
+    #[test]
+    fn test_parse_proxied_image() {
+        use super::parse_proxied_image;
+
+        assert_eq!((2000, "1*abc.png"), parse_proxied_image("1*abc.png"));
+        assert_eq!((640, "1*abc.png"), parse_proxied_image("640/1*abc.png"));
+        assert_eq!((4000, "1*abc.png"), parse_proxied_image("99999/1*abc.png"));
+        assert_eq!((1, "1*abc.png"), parse_proxied_image("0/1*abc.png"));
+        assert_eq!((2000, "a/b.png"), parse_proxied_image("a/b.png"));
+    }
+
+    #[test]
+    fn test_external_links_open_in_new_tab() {
+        let html = Content::hyperlink("https://example.com/page", vec![Content::text("x")], None)
+            .to_string();
+
+        assert!(html.contains(r#" target="_blank""#));
+        assert!(html.contains(r#" rel="noopener noreferrer""#));
+        assert!(html.contains(r#" href="https://example.com/page""#));
+    }
+
+    #[test]
+    fn test_medium_and_anchor_links_keep_default_target() {
+        for href in [
+            "https://medium.com/@jdoe",
+            "https://jdoe.medium.com/post",
+            "#note-1",
+            "/a1b2c3d4e5f6",
+        ] {
+            let html = Content::hyperlink(href, vec![Content::text("x")], None).to_string();
+
+            assert_eq!(format!(r#"<a href="{}">x</a>"#, href), html);
+        }
+    }
+
+    #[test]
+    fn test_link_host() {
+        assert_eq!(
+            Some(String::from("example.com")),
+            super::link_host("https://user@WWW.Example.com:8080/path?q#f")
+        );
+        assert_eq!(None, super::link_host("/relative"));
+        assert_eq!(None, super::link_host("#fragment"));
+        assert_eq!(None, super::link_host("mailto:someone@example.com"));
+    }
+
+    #[test]
+    fn test_link_domains_keep_lists_aligned() {
+        let paragraphs = vec![
+            iframe_paragraph("https://example.com/embed"),
+            text_paragraph("ULI", "one"),
+            text_paragraph("ULI", "two"),
+            text_paragraph("OLI", "first"),
+            text_paragraph("P", "last"),
+        ];
+        let mut json = serde_json::to_value(sample_post()).unwrap();
+        json["content"]["bodyModel"]["paragraphs"] = serde_json::to_value(&paragraphs).unwrap();
+        let post: PostResult = serde_json::from_value(json).unwrap();
+        let options = RenderOptions {
+            annotate_link_domains: true,
+            ..Default::default()
+        };
+
+        let html = post.render_with(&options).unwrap().to_string();
+        assert!(html.contains(concat!(
+            r#">IFRAME: a tweet</a><span class="link-domain"> (example.com)</span>"#,
+            "<ul><li>one</li><li>two</li></ul><ol><li>first</li></ol><p>last</p>"
+        )));
+    }
+
+    #[test]
+    fn test_external_links_get_their_domain() {
+        let mut body = vec![Content::tag(
+            "p",
+            None,
+            Some(vec![
+                Content::hyperlink(
+                    "https://example.com/page",
+                    vec![Content::text("external")],
+                    None,
+                ),
+                Content::hyperlink(
+                    "https://medium.com/@jdoe",
+                    vec![Content::text("internal")],
+                    None,
+                ),
+            ]),
+        )];
+        super::annotate_link_domains(&mut body);
+        let html = body[0].to_string();
+
+        assert!(html.contains(r#" href="https://example.com/page""#));
+        assert!(html.contains(
+            r#">external</a><span class="link-domain"> (example.com)</span><a href="https://medium.com/@jdoe">internal</a></p>"#
+        ));
+    }
 }
@@ -0,0 +1,68 @@
+//! replaces emoji in text with their unicode name or strips them. emoji are matched
+//! per grapheme cluster, so zwj sequences and skin tone modifiers stay one unit.
+
+use unicode_segmentation::UnicodeSegmentation;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum EmojiMode {
+    #[default]
+    Keep,
+    /// "👋🏽" becomes ":waving_hand_medium_skin_tone:"
+    Describe,
+    Strip,
+}
+
+fn short_name(emoji: &emojis::Emoji) -> String {
+    let words: Vec<String> = emoji
+        .name()
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .map(str::to_lowercase)
+        .collect();
+    format!(":{}:", words.join("_"))
+}
+
+pub fn replace_emoji(text: &str, mode: EmojiMode) -> String {
+    if mode == EmojiMode::Keep {
+        return text.to_string();
+    }
+
+    text.graphemes(true)
+        .map(|grapheme| match (emojis::get(grapheme), mode) {
+            (Some(emoji), EmojiMode::Describe) => short_name(emoji),
+            (Some(_), _) => String::new(),
+            (None, _) => grapheme.to_string(),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use crate::emoji::{replace_emoji, EmojiMode};
+
+    #[test]
+    fn test_describes_skin_tone_emoji_as_one_unit() {
+        assert_eq!(
+            "L :waving_hand_medium_skin_tone: R",
+            replace_emoji("L 👋🏽 R", EmojiMode::Describe)
+        );
+    }
+
+    #[test]
+    fn test_describes_zwj_sequence_as_one_unit() {
+        assert_eq!(
+            "a :family_man_woman_girl: b",
+            replace_emoji("a 👨‍👩‍👧 b", EmojiMode::Describe)
+        );
+    }
+
+    #[test]
+    fn test_strips_emoji() {
+        assert_eq!("L  R", replace_emoji("L 👋🏽 R", EmojiMode::Strip));
+    }
+
+    #[test]
+    fn test_keeps_emoji_by_default() {
+        assert_eq!("L 👋🏽 R", replace_emoji("L 👋🏽 R", EmojiMode::default()));
+    }
+}
@@ -0,0 +1,143 @@
+//! atom feeds of an author's posts, each entry linking to the article as rendered
+//! by mediumrare
+
+use crate::client::PostResult;
+use crate::content::escape_attribute;
+use crate::timestamp::DateTime;
+
+fn xml_element(name: &str, text: &str) -> String {
+    format!("<{name}>{}</{name}>", escape_attribute(text))
+}
+
+/// `base_url` is where mediumrare is deployed, articles are linked as `<base_url>/<post id>`
+pub fn atom_feed(username: &str, posts: &[PostResult], base_url: &str) -> String {
+    let username = username.trim_start_matches('@');
+    let base_url = base_url.trim_end_matches('/');
+    let updated = posts
+        .iter()
+        .map(PostResult::updated_at)
+        .max()
+        .unwrap_or_default();
+
+    let mut out = String::from(r#"<?xml version="1.0" encoding="utf-8"?>"#);
+    out.push_str("\n<feed xmlns=\"http://www.w3.org/2005/Atom\">\n");
+    out.push_str(&format!(
+        "  {}\n",
+        xml_element("id", &format!("https://medium.com/@{}", username))
+    ));
+    out.push_str(&format!(
+        "  {}\n",
+        xml_element("title", &format!("@{} on medium", username))
+    ));
+    out.push_str(&format!(
+        "  {}\n",
+        xml_element("updated", &DateTime::from_epoch_ms(updated).to_rfc3339())
+    ));
+    out.push_str(&format!(
+        "  <link rel=\"alternate\" href=\"https://medium.com/@{}\"/>\n",
+        escape_attribute(username)
+    ));
+    for post in posts {
+        out.push_str(&entry(post, base_url));
+    }
+    out.push_str("</feed>\n");
+    out
+}
+
+fn entry(post: &PostResult, base_url: &str) -> String {
+    let lines = [
+        xml_element("id", &post.medium_url),
+        xml_element("title", &post.title),
+        format!(
+            "<link rel=\"alternate\" href=\"{}/{}\"/>",
+            escape_attribute(base_url),
+            escape_attribute(post.id())
+        ),
+        xml_element("published", &post.published_time().to_rfc3339()),
+        xml_element("updated", &post.updated_time().to_rfc3339()),
+        format!(
+            "<author>{}</author>",
+            xml_element("name", &post.creator.name)
+        ),
+        xml_element("summary", post.summary()),
+    ];
+
+    let mut out = String::from("  <entry>\n");
+    for line in lines {
+        out.push_str(&format!("    {}\n", line));
+    }
+    out.push_str("  </entry>\n");
+    out
+}
+
+#[cfg(test)]
+mod test {
+    use crate::client::{PostResult, QueryResponse};
+    use crate::feed::atom_feed;
+
+    fn sample_post() -> PostResult {
+        serde_json::from_str::<QueryResponse>(include_str!("../test/post.json"))
+            .unwrap()
+            .get_post()
+    }
+
+    #[test]
+    fn test_feed_is_well_formed_atom() {
+        let first = sample_post();
+        let mut second = sample_post();
+        second.id = String::from("0123456789ab");
+        second.title = String::from("Less <than> & more");
+
+        let feed = atom_feed("@jdoe", &[first, second], "https://mediumrare.example.com/");
+        let document = roxmltree::Document::parse(&feed).unwrap();
+        let root = document.root_element();
+        assert_eq!("feed", root.tag_name().name());
+        assert_eq!(
+            Some("http://www.w3.org/2005/Atom"),
+            root.tag_name().namespace()
+        );
+
+        let entries: Vec<_> = root
+            .children()
+            .filter(|node| node.has_tag_name("entry"))
+            .collect();
+        assert_eq!(2, entries.len());
+
+        fn child<'a, 'i>(entry: roxmltree::Node<'a, 'i>, name: &str) -> roxmltree::Node<'a, 'i> {
+            entry
+                .children()
+                .find(|node| node.has_tag_name(name))
+                .unwrap()
+        }
+        assert_eq!(Some("A Sample Post"), child(entries[0], "title").text());
+        assert_eq!(
+            Some("Less <than> & more"),
+            child(entries[1], "title").text()
+        );
+        assert_eq!(
+            Some("https://mediumrare.example.com/0123456789ab"),
+            child(entries[1], "link").attribute("href")
+        );
+        assert_eq!(
+            Some("Jane Doe"),
+            child(child(entries[0], "author"), "name").text()
+        );
+        assert_eq!(
+            Some("2020-05-15T06:01:09Z"),
+            child(entries[0], "published").text()
+        );
+    }
+
+    #[test]
+    fn test_empty_feed() {
+        let feed = atom_feed("jdoe", &[], "https://mediumrare.example.com");
+        let document = roxmltree::Document::parse(&feed).unwrap();
+
+        assert!(!document
+            .root_element()
+            .children()
+            .any(|node| node.has_tag_name("entry")));
+        assert!(feed.contains("<title>@jdoe on medium</title>"));
+        assert!(feed.contains("<updated>1970-01-01T00:00:00Z</updated>"));
+    }
+}
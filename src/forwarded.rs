@@ -0,0 +1,155 @@
+//! resolves the address of the actual client when running behind reverse proxies.
+//! `X-Forwarded-For` is only trusted when it was added by one of our own proxies,
+//! otherwise any client could claim to be someone else.
+
+use std::net::IpAddr;
+use std::str::FromStr;
+
+#[derive(Debug, thiserror::Error, PartialEq)]
+#[error("invalid cidr: {0}")]
+pub struct InvalidCidr(String);
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Cidr {
+    network: IpAddr,
+    prefix_len: u8,
+}
+
+impl FromStr for Cidr {
+    type Err = InvalidCidr;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || InvalidCidr(s.to_string());
+        let (addr, prefix_len) = match s.trim().split_once('/') {
+            Some((addr, len)) => (addr, Some(len)),
+            None => (s.trim(), None),
+        };
+
+        let network: IpAddr = addr.parse().map_err(|_| invalid())?;
+        let max_len = if network.is_ipv4() { 32 } else { 128 };
+        let prefix_len = match prefix_len {
+            Some(len) => len.parse().map_err(|_| invalid())?,
+            None => max_len,
+        };
+        if prefix_len > max_len {
+            return Err(invalid());
+        }
+
+        Ok(Cidr {
+            network,
+            prefix_len,
+        })
+    }
+}
+
+impl Cidr {
+    pub fn contains(&self, ip: &IpAddr) -> bool {
+        match (self.network, ip) {
+            (IpAddr::V4(net), IpAddr::V4(ip)) => prefix_matches(
+                u32::from(net).into(),
+                u32::from(*ip).into(),
+                32,
+                self.prefix_len,
+            ),
+            (IpAddr::V6(net), IpAddr::V6(ip)) => {
+                prefix_matches(u128::from(net), u128::from(*ip), 128, self.prefix_len)
+            }
+            _ => false,
+        }
+    }
+}
+
+fn prefix_matches(network: u128, ip: u128, bits: u8, prefix_len: u8) -> bool {
+    if prefix_len == 0 {
+        return true;
+    }
+    let shift = bits - prefix_len;
+    network >> shift == ip >> shift
+}
+
+fn is_trusted(ip: &IpAddr, trusted: &[Cidr]) -> bool {
+    trusted.iter().any(|cidr| cidr.contains(ip))
+}
+
+/// walks the `X-Forwarded-For` chain from the right, skipping our own proxies, and
+/// returns the first address that was not added by a trusted proxy
+pub fn client_ip(peer: IpAddr, forwarded_for: Option<&str>, trusted: &[Cidr]) -> IpAddr {
+    if !is_trusted(&peer, trusted) {
+        return peer;
+    }
+
+    let mut client = peer;
+    for hop in forwarded_for.unwrap_or("").rsplit(',') {
+        let Ok(ip) = hop.trim().parse::<IpAddr>() else {
+            break;
+        };
+        client = ip;
+        if !is_trusted(&ip, trusted) {
+            break;
+        }
+    }
+
+    client
+}
+
+#[cfg(test)]
+mod test {
+    use crate::forwarded::{client_ip, Cidr, InvalidCidr};
+    use std::net::IpAddr;
+
+    fn ip(s: &str) -> IpAddr {
+        s.parse().unwrap()
+    }
+
+    fn trusted() -> Vec<Cidr> {
+        vec!["10.0.0.0/8".parse().unwrap(), "::1".parse().unwrap()]
+    }
+
+    #[test]
+    fn test_parses_cidrs() {
+        let cidr: Cidr = "192.168.0.0/16".parse().unwrap();
+        assert!(cidr.contains(&ip("192.168.12.1")));
+        assert!(!cidr.contains(&ip("192.169.0.1")));
+        assert!(!cidr.contains(&ip("::1")));
+
+        assert_eq!(
+            Err(InvalidCidr("10.0.0.0/33".into())),
+            "10.0.0.0/33".parse::<Cidr>()
+        );
+        assert!("nonsense".parse::<Cidr>().is_err());
+    }
+
+    #[test]
+    fn test_untrusted_peer_ignores_forwarded_for() {
+        assert_eq!(
+            ip("203.0.113.7"),
+            client_ip(ip("203.0.113.7"), Some("1.2.3.4"), &trusted())
+        );
+    }
+
+    #[test]
+    fn test_trusted_peer_uses_forwarded_for() {
+        assert_eq!(
+            ip("1.2.3.4"),
+            client_ip(ip("10.0.0.2"), Some("1.2.3.4"), &trusted())
+        );
+    }
+
+    #[test]
+    fn test_skips_trusted_hops_but_not_spoofed_ones() {
+        // the client sent a fake "6.6.6.6", our proxies appended the real address
+        assert_eq!(
+            ip("1.2.3.4"),
+            client_ip(
+                ip("10.0.0.2"),
+                Some("6.6.6.6, 1.2.3.4, 10.0.0.3"),
+                &trusted()
+            )
+        );
+    }
+
+    #[test]
+    fn test_trusted_peer_without_header() {
+        assert_eq!(ip("::1"), client_ip(ip("::1"), None, &trusted()));
+    }
+}
@@ -1,24 +1,246 @@
+use crate::client::PostResult;
+use crate::content::{image_url, Render, RenderOptions};
+use crate::math::{has_math, KATEX_VERSION};
 use maud::{html, PreEscaped, DOCTYPE};
 
-pub fn html_page(title: &str, body: &str) -> String {
-    let css = r#" body { background-color: #111; color: #eee; font-family: sans-serif; font-size: 130%; }
-                    article { width: 60rem; margin: auto }
+pub const STYLESHEET: &str = r#" body { background-color: #111; color: #eee; font-family: sans-serif; font-size: 130%; }
+                    article { max-width: 60rem; margin: auto; padding: 0 1rem; }
                     img { max-width: 100% }
-                    pre { background-color: #000; padding: 1rem; border-radius: .5rem; overflow-y: scroll; }
+                    img.hero { display: block; margin: 0 auto 1rem; }
+                    figure { margin: 1rem 0; }
+                    .embed iframe { display: block; width: 100%; height: auto; margin: 1rem auto; }
+                    figcaption { font-size: 80%; text-align: center; color: #aaa; }
+                    pre { background-color: #000; padding: 1rem; border-radius: .5rem; overflow-y: scroll; white-space: pre; tab-size: 4; }
                     code { background-color: #000; padding: .25rem; border-radius: .5rem; }
                     blockquote { background-color: #333; margin: 0; padding: 1rem;  padding-left: 2rem; border-left: 5px solid gray; }
+                    blockquote.pullquote { background-color: transparent; border-left: none; font-size: 140%; font-style: italic; text-align: center; }
                     li { margin-left: 2rem; }
                     a { color: cornflowerblue }
-                    .post-head {  background-color: #333; margin: 0; padding: 1rem; font-size: 80%; } "#;
+                    .post-head {  background-color: #333; margin: 0; padding: 1rem; font-size: 80%; }
+                    .post-tags, .post-topics { padding: 0; font-size: 80%; }
+                    .post-tags li, .post-topics li { display: inline; margin: 0 .5rem 0 0; }
+                    .post-topics li { color: #aaa; }
+                    .post-author { margin-top: 2rem; border-top: 1px solid gray; font-size: 90%; }
+                    .math-block { overflow-x: auto; }
+                    .post-error { max-width: 60rem; margin: 1rem auto; padding: 1rem; border-left: 5px solid firebrick; }
+                    .skip-link { position: absolute; left: -10000px; top: auto; }
+                    .skip-link:focus { left: 1rem; top: 1rem; padding: .5rem; background-color: #000; z-index: 1; } "#;
+
+/// overrides the dark colors of `STYLESHEET`, for readers who prefer light pages
+const LIGHT_COLORS: &str = r#" body { background-color: #fdfdfd; color: #222; }
+                    pre, code { background-color: #f0f0f0; }
+                    blockquote, .post-head { background-color: #eee; }
+                    figcaption, .post-topics li { color: #555; }
+                    a { color: #0645ad }
+                    .skip-link:focus { background-color: #fff; } "#;
+
+/// the stylesheet pages are rendered with
+#[derive(Debug, Clone)]
+pub struct Theme {
+    css: String,
+}
+
+impl Theme {
+    /// dark, unless the reader's system prefers light colors
+    pub fn auto() -> Theme {
+        Theme {
+            css: format!(
+                "{}\n@media (prefers-color-scheme: light) {{{}}}",
+                STYLESHEET, LIGHT_COLORS
+            ),
+        }
+    }
+
+    pub fn dark() -> Theme {
+        Theme::custom(STYLESHEET)
+    }
+
+    pub fn light() -> Theme {
+        Theme {
+            css: format!("{}{}", STYLESHEET, LIGHT_COLORS),
+        }
+    }
+
+    /// replaces the built in stylesheet entirely
+    pub fn custom(css: &str) -> Theme {
+        Theme {
+            css: css.to_string(),
+        }
+    }
+
+    pub fn css(&self) -> &str {
+        &self.css
+    }
+}
+
+impl Default for Theme {
+    fn default() -> Self {
+        Theme::auto()
+    }
+}
+
+/// what link unfurlers show when the page is shared
+#[derive(Debug)]
+pub struct PageMeta {
+    pub title: String,
+    pub description: String,
+    /// absolute url of the preview image
+    pub image: Option<String>,
+}
+
+/// parts of the document head that depend on what ended up in the body
+#[derive(Debug, Default)]
+pub struct PageOptions {
+    /// load katex to typeset math markup
+    pub math: bool,
+    /// load twitter's widgets.js to turn tweet blockquotes into embeds
+    pub twitter_widget: bool,
+    /// link the stylesheet from this url instead of inlining it into every page
+    pub stylesheet_href: Option<String>,
+    /// the original article, so search engines attribute the content to medium
+    pub canonical_url: Option<String>,
+    /// opengraph and twitter card tags
+    pub meta: Option<PageMeta>,
+    /// schema.org data for search engines, a serialized json object, see `article_json_ld`
+    pub json_ld: Option<String>,
+    /// inlined unless `stylesheet_href` is set
+    pub theme: Theme,
+}
+
+/// the full document for a post, the rendered article plus a head with everything
+/// needed to display it and to unfurl links to it
+pub struct Page<'a> {
+    post: &'a PostResult,
+    title: Option<String>,
+    theme: Theme,
+    stylesheet_href: Option<String>,
+}
+
+impl<'a> Page<'a> {
+    pub fn new(post: &'a PostResult) -> Page<'a> {
+        Page {
+            post,
+            title: None,
+            theme: Theme::default(),
+            stylesheet_href: None,
+        }
+    }
+
+    /// replaces the page title only, the article keeps the post's own
+    pub fn title<S: Into<String>>(mut self, title: S) -> Page<'a> {
+        self.title = Some(title.into());
+        self
+    }
+
+    pub fn theme(mut self, theme: Theme) -> Page<'a> {
+        self.theme = theme;
+        self
+    }
+
+    /// see `PageOptions::stylesheet_href`
+    pub fn stylesheet_href(mut self, href: Option<String>) -> Page<'a> {
+        self.stylesheet_href = href;
+        self
+    }
+
+    pub fn render_with(&self, options: &RenderOptions) -> anyhow::Result<String> {
+        let content = self.post.render_with(options)?;
+        let title = self.title.as_deref().unwrap_or(&self.post.title);
+        let page_options = PageOptions {
+            math: has_math(&content),
+            twitter_widget: content.contains_class("twitter-tweet"),
+            stylesheet_href: self.stylesheet_href.clone(),
+            canonical_url: Some(self.post.medium_url.clone()),
+            theme: self.theme.clone(),
+            meta: Some(PageMeta {
+                title: title.to_string(),
+                description: self.post.summary().to_string(),
+                // unfurlers need an absolute url, which the image proxy route isn't
+                image: self
+                    .post
+                    .preview_image_id()
+                    .map(|id| image_url(id, &RenderOptions::default())),
+            }),
+            json_ld: Some(article_json_ld(self.post)),
+        };
+
+        Ok(html_page_with(title, &content.to_string(), &page_options))
+    }
+}
+
+/// the schema.org `Article` describing `post`, ready to be put into a
+/// `<script type="application/ld+json">`
+pub fn article_json_ld(post: &PostResult) -> String {
+    let mut article = serde_json::json!({
+        "@context": "https://schema.org",
+        "@type": "Article",
+        "headline": post.title,
+        "author": {
+            "@type": "Person",
+            "name": post.creator.name,
+            "url": format!("https://medium.com/@{}", post.creator.username),
+        },
+        "datePublished": post.published_time().to_rfc3339(),
+        "dateModified": post.updated_time().to_rfc3339(),
+        "url": post.medium_url,
+    });
+    if let Some(id) = post.preview_image_id() {
+        article["image"] = image_url(id, &RenderOptions::default()).into();
+    }
+
+    // a `</script>` in the title must not end the script element early
+    article.to_string().replace("</", "<\\/")
+}
+
+pub fn html_page(title: &str, body: &str) -> String {
+    html_page_with(title, body, &PageOptions::default())
+}
+
+pub fn html_page_with(title: &str, body: &str, options: &PageOptions) -> String {
+    let katex = format!("https://cdn.jsdelivr.net/npm/katex@{}/dist", KATEX_VERSION);
     html! {
         (DOCTYPE)
         html {
             head {
-                style { (css) }
+                meta charset="utf-8";
+                meta name="viewport" content="width=device-width, initial-scale=1";
+                @if let Some(href) = &options.stylesheet_href {
+                    link rel="stylesheet" href=(href);
+                } @else {
+                    style { (PreEscaped(options.theme.css())) }
+                }
                 title { (title) }
+                @if let Some(url) = &options.canonical_url {
+                    link rel="canonical" href=(url);
+                }
+                @if let Some(meta) = &options.meta {
+                    meta property="og:type" content="article";
+                    meta property="og:title" content=(meta.title);
+                    meta property="og:description" content=(meta.description);
+                    @if let Some(image) = &meta.image {
+                        meta property="og:image" content=(image);
+                        meta name="twitter:card" content="summary_large_image";
+                    } @else {
+                        meta name="twitter:card" content="summary";
+                    }
+                }
+                @if let Some(json_ld) = &options.json_ld {
+                    script type="application/ld+json" { (PreEscaped(json_ld)) }
+                }
+                @if options.math {
+                    link rel="stylesheet" href={ (katex) "/katex.min.css" };
+                    script defer src={ (katex) "/katex.min.js" } {}
+                    script defer src={ (katex) "/contrib/auto-render.min.js" } onload="renderMathInElement(document.body);" {}
+                }
+                @if options.twitter_widget {
+                    script async src="https://platform.twitter.com/widgets.js" charset="utf-8" {}
+                }
             }
             body {
-                (PreEscaped(body))
+                a.skip-link href="#content" { "Skip to content" }
+                main #content {
+                    (PreEscaped(body))
+                }
             }
         }
     }
@@ -47,3 +269,220 @@ pub fn home() -> String {
         .into_string(),
     )
 }
+
+#[cfg(test)]
+mod test {
+    use crate::client::QueryResponse;
+    use crate::content::RenderOptions;
+    use crate::html::{
+        article_json_ld, html_page, html_page_with, Page, PageMeta, PageOptions, Theme,
+    };
+    use serde_json::Value;
+
+    #[test]
+    fn test_page_renders_post_with_head() {
+        let post = serde_json::from_str::<QueryResponse>(include_str!("../test/post.json"))
+            .unwrap()
+            .get_post();
+        let page = Page::new(&post)
+            .theme(Theme::light())
+            .render_with(&RenderOptions::default())
+            .unwrap();
+
+        assert!(page.starts_with("<!DOCTYPE html><html><head>"));
+        assert!(page.contains("<title>A Sample Post</title>"));
+        assert!(page.contains(r#"<meta property="og:title" content="A Sample Post">"#));
+        assert!(page.contains(Theme::light().css()));
+        assert!(page.contains(r#"<main id="content"><article>"#));
+        assert!(page.contains("<h3>A Sample Post</h3>"));
+    }
+
+    #[test]
+    fn test_page_describes_article_as_json_ld() {
+        let post = serde_json::from_str::<QueryResponse>(include_str!("../test/post.json"))
+            .unwrap()
+            .get_post();
+        let page = Page::new(&post)
+            .render_with(&RenderOptions::default())
+            .unwrap();
+
+        let json_ld = page
+            .split(r#"<script type="application/ld+json">"#)
+            .nth(1)
+            .and_then(|rest| rest.split("</script>").next())
+            .unwrap();
+        let article: Value = serde_json::from_str(json_ld).unwrap();
+        assert_eq!("Article", article["@type"]);
+        assert_eq!("A Sample Post", article["headline"]);
+        assert_eq!("Jane Doe", article["author"]["name"]);
+        assert_eq!("2020-05-15T06:01:09Z", article["datePublished"]);
+        assert_eq!(
+            "https://miro.medium.com/v2/resize:fit:2000/1*preview.png",
+            article["image"]
+        );
+    }
+
+    #[test]
+    fn test_json_ld_cannot_close_its_script() {
+        let mut post = serde_json::from_str::<QueryResponse>(include_str!("../test/post.json"))
+            .unwrap()
+            .get_post();
+        post.title = String::from("</script><script>alert(1)</script>");
+
+        let json_ld = article_json_ld(&post);
+        assert!(!json_ld.contains("</"));
+        let article: Value = serde_json::from_str(&json_ld).unwrap();
+        assert_eq!("</script><script>alert(1)</script>", article["headline"]);
+    }
+
+    #[test]
+    fn test_katex_is_only_included_with_math() {
+        assert!(!html_page("title", "<p>body</p>").contains("katex"));
+
+        let page = html_page_with(
+            "title",
+            "<p>body</p>",
+            &PageOptions {
+                math: true,
+                ..Default::default()
+            },
+        );
+        assert_eq!(1, page.matches("katex.min.js").count());
+        assert!(page.contains("auto-render.min.js"));
+    }
+
+    #[test]
+    fn test_skip_link_targets_main_content() {
+        let page = html_page("title", "<article>body</article>");
+
+        assert!(
+            page.contains(r##"<body><a class="skip-link" href="#content">Skip to content</a>"##)
+        );
+        assert!(page.contains(r#"<main id="content"><article>body</article></main>"#));
+    }
+
+    #[test]
+    fn test_head_sets_charset_and_viewport() {
+        let page = html_page("title", "");
+
+        assert!(page.contains(r#"<head><meta charset="utf-8">"#));
+        assert!(page
+            .contains(r#"<meta name="viewport" content="width=device-width, initial-scale=1">"#));
+    }
+
+    #[test]
+    fn test_light_theme_has_light_background() {
+        let page = html_page_with(
+            "title",
+            "",
+            &PageOptions {
+                theme: Theme::light(),
+                ..Default::default()
+            },
+        );
+
+        let style = page
+            .split_once("<style>")
+            .and_then(|(_, rest)| rest.split_once("</style>"))
+            .unwrap()
+            .0;
+        let last_body_rule = style.rsplit("body {").next().unwrap();
+        assert!(last_body_rule.starts_with(" background-color: #fdfdfd;"));
+    }
+
+    #[test]
+    fn test_default_theme_follows_color_scheme() {
+        let css = Theme::default().css().to_string();
+
+        let (dark, light) = css
+            .split_once("@media (prefers-color-scheme: light) {")
+            .unwrap();
+        assert!(dark.contains("body { background-color: #111;"));
+        assert!(light.contains("body { background-color: #fdfdfd;"));
+        assert!(!Theme::dark().css().contains("prefers-color-scheme"));
+    }
+
+    #[test]
+    fn test_custom_theme_replaces_stylesheet() {
+        let page = html_page_with(
+            "title",
+            "",
+            &PageOptions {
+                theme: Theme::custom(r#"body > p { font-family: "Serif"; }"#),
+                ..Default::default()
+            },
+        );
+
+        assert!(page.contains(r#"<style>body > p { font-family: "Serif"; }</style>"#));
+    }
+
+    #[test]
+    fn test_canonical_link_points_at_original() {
+        assert!(!html_page("title", "").contains("canonical"));
+
+        let page = html_page_with(
+            "title",
+            "",
+            &PageOptions {
+                canonical_url: Some(String::from("https://medium.com/@jdoe/post-a1b2c3d4e5f6")),
+                ..Default::default()
+            },
+        );
+        assert!(page.contains(
+            r#"<link rel="canonical" href="https://medium.com/@jdoe/post-a1b2c3d4e5f6">"#
+        ));
+    }
+
+    #[test]
+    fn test_card_type_depends_on_image() {
+        let meta = |image: Option<&str>| PageOptions {
+            meta: Some(PageMeta {
+                title: String::from("title"),
+                description: String::from("a \"quoted\" description"),
+                image: image.map(String::from),
+            }),
+            ..Default::default()
+        };
+
+        let page = html_page_with("title", "", &meta(Some("https://example.com/image.png")));
+        assert!(page.contains(
+            r#"<meta property="og:description" content="a &quot;quoted&quot; description">"#
+        ));
+        assert!(page.contains(r#"<meta name="twitter:card" content="summary_large_image">"#));
+
+        let page = html_page_with("title", "", &meta(None));
+        assert!(!page.contains("og:image"));
+        assert!(page.contains(r#"<meta name="twitter:card" content="summary">"#));
+    }
+
+    #[test]
+    fn test_twitter_widget_script_is_opt_in() {
+        assert!(!html_page("title", "").contains("widgets.js"));
+
+        let page = html_page_with(
+            "title",
+            "",
+            &PageOptions {
+                twitter_widget: true,
+                ..Default::default()
+            },
+        );
+        assert_eq!(1, page.matches("platform.twitter.com/widgets.js").count());
+    }
+
+    #[test]
+    fn test_stylesheet_is_inlined_by_default() {
+        assert!(html_page("title", "").contains("<style>"));
+
+        let page = html_page_with(
+            "title",
+            "",
+            &PageOptions {
+                stylesheet_href: Some(String::from("/style.css")),
+                ..Default::default()
+            },
+        );
+        assert!(page.contains(r#"<link rel="stylesheet" href="/style.css">"#));
+        assert!(!page.contains("<style>"));
+    }
+}
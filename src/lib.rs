@@ -1,3 +1,17 @@
 pub mod client;
+pub mod compression;
 pub mod content;
+#[cfg(feature = "emoji")]
+pub mod emoji;
+pub mod feed;
+pub mod forwarded;
+pub mod html;
+pub mod markdown;
+pub mod math;
+#[cfg(test)]
+mod mock_client;
+pub mod prefetch;
+#[cfg(test)]
+mod test_support;
 pub mod text_markup;
+pub mod timestamp;
@@ -1,70 +1,913 @@
-mod client;
-mod content;
-mod html;
-mod text_markup;
-
 use lambda_http::{
-    http::{header::CONTENT_TYPE, HeaderValue, StatusCode},
-    service_fn, Error, IntoResponse, Request, RequestExt, Response,
+    http::{
+        header::{
+            ACCEPT_ENCODING, CACHE_CONTROL, CONTENT_ENCODING, CONTENT_TYPE, ETAG, IF_NONE_MATCH,
+            VARY,
+        },
+        HeaderValue, StatusCode,
+    },
+    request::RequestContext,
+    service_fn, Body, Error, Request, RequestExt, Response,
 };
 
-use client::{Client, PostDataClient};
-use content::Render;
 use lazy_static::lazy_static;
-use std::{string::ToString, time::Instant};
+use mediumrare::client::{
+    self, AsyncPostDataClient, BatchEntry, Client, Image, PostResult, SpawnBlockingClient,
+};
+use mediumrare::compression::{self, MIN_COMPRESSED_LEN};
+use mediumrare::content::{parse_proxied_image, Render, RenderOptions, IMAGE_PROXY_PREFIX};
+use mediumrare::forwarded::{self, Cidr};
+use mediumrare::html::{self, Page, PageOptions, Theme};
+use mediumrare::math;
+use mediumrare::prefetch;
+use std::{net::IpAddr, string::ToString, time::Instant};
+
+#[cfg(test)]
+mod test_support;
 
 lazy_static! {
-    static ref CLIENT: Client = Client;
+    static ref CLIENT: SpawnBlockingClient<Client> =
+        SpawnBlockingClient::new(match &CONFIG.graphql_endpoint {
+            Some(endpoint) => Client::new().with_endpoint(endpoint.as_str()),
+            None => Client::default(),
+        });
+    static ref CONFIG: Config = Config::from_env();
+}
+
+/// runtime configuration, read from the environment once per lambda instance
+struct Config {
+    /// serve images through `/img/<width>/<id>` instead of linking the medium cdn
+    image_proxy: bool,
+    /// reference the stylesheet via `/style.css` so browsers can cache it
+    link_stylesheet: bool,
+    /// embed tweets with twitter's widget script instead of linking them
+    twitter_widget: bool,
+    /// image shown when a medium image fails to load
+    fallback_image: Option<String>,
+    /// only render posts by these medium usernames
+    allowed_authors: Option<Vec<String>>,
+    /// proxies allowed to tell us the client address via `X-Forwarded-For`
+    trusted_proxies: Vec<Cidr>,
+    /// `light` or `dark`, follows the reader's color scheme by default
+    theme: Theme,
+    /// query this graphql url instead of medium's, e.g. a mirror
+    graphql_endpoint: Option<String>,
+    /// warm medium's image cdn for every post we render. best effort only: lambda
+    /// freezes the environment once the response is sent, so prefetches still
+    /// running then only continue on the next invocation, if at all
+    prefetch_images: bool,
+    /// the width images are resized to by medium's cdn
+    image_width: Option<u32>,
+}
+
+impl Config {
+    fn from_env() -> Config {
+        Config {
+            image_proxy: env_flag("MEDIUMRARE_IMAGE_PROXY"),
+            twitter_widget: env_flag("MEDIUMRARE_TWITTER_WIDGET"),
+            link_stylesheet: env_flag("MEDIUMRARE_LINK_STYLESHEET"),
+            fallback_image: std::env::var("MEDIUMRARE_FALLBACK_IMAGE").ok(),
+            allowed_authors: std::env::var("MEDIUMRARE_ALLOWED_AUTHORS")
+                .ok()
+                .map(|list| parse_list(&list)),
+            trusted_proxies: parse_cidr_list(
+                &std::env::var("MEDIUMRARE_TRUSTED_PROXIES").unwrap_or_default(),
+            ),
+            theme: match std::env::var("MEDIUMRARE_THEME").as_deref() {
+                Ok("light") => Theme::light(),
+                Ok("dark") => Theme::dark(),
+                _ => Theme::auto(),
+            },
+            graphql_endpoint: std::env::var("MEDIUMRARE_GRAPHQL_ENDPOINT").ok(),
+            prefetch_images: env_flag("MEDIUMRARE_PREFETCH_IMAGES"),
+            image_width: std::env::var("MEDIUMRARE_IMAGE_WIDTH")
+                .ok()
+                .and_then(|width| width.parse().ok()),
+        }
+    }
+}
+
+fn parse_list(list: &str) -> Vec<String> {
+    list.split(',')
+        .map(str::trim)
+        .filter(|entry| !entry.is_empty())
+        .map(String::from)
+        .collect()
+}
+
+fn parse_cidr_list(list: &str) -> Vec<Cidr> {
+    parse_list(list)
+        .into_iter()
+        .filter_map(|entry| match entry.parse() {
+            Ok(cidr) => Some(cidr),
+            Err(err) => {
+                tracing::warn!(error = %err, "ignoring trusted proxy");
+                None
+            }
+        })
+        .collect()
+}
+
+fn peer_ip(event: &Request) -> Option<IpAddr> {
+    let source_ip = match event.request_context_ref()? {
+        RequestContext::ApiGatewayV1(ctx) => ctx.identity.source_ip.as_deref(),
+        RequestContext::ApiGatewayV2(ctx) => ctx.http.source_ip.as_deref(),
+        _ => None,
+    };
+    source_ip?.parse().ok()
+}
+
+fn request_client_ip(event: &Request, trusted: &[Cidr]) -> Option<IpAddr> {
+    let forwarded_for = event
+        .headers()
+        .get("X-Forwarded-For")
+        .and_then(|v| v.to_str().ok());
+    Some(forwarded::client_ip(
+        peer_ip(event)?,
+        forwarded_for,
+        trusted,
+    ))
+}
+
+fn env_flag(name: &str) -> bool {
+    matches!(std::env::var(name).as_deref(), Ok("1") | Ok("true"))
 }
 
 #[derive(Debug, thiserror::Error)]
 enum LocalError {
     #[error("client error: {0:?}")]
     ClientError(#[from] client::ClientError),
+
+    #[error("posts by {0} are not served here")]
+    AuthorNotAllowed(String),
+}
+
+/// rejects posts by authors outside the configured allowlist, without an allowlist
+/// every author is served
+fn check_author(post: &PostResult, allowed_authors: Option<&[String]>) -> Result<(), LocalError> {
+    match allowed_authors {
+        Some(allowed) if !allowed.contains(&post.creator.username) => {
+            Err(LocalError::AuthorNotAllowed(post.creator.username.clone()))
+        }
+        _ => Ok(()),
+    }
 }
 
-fn render_post(post_id: &str) -> Result<String, LocalError> {
+async fn fetch_post(post_id: &str) -> Result<PostResult, LocalError> {
+    fetch_post_with(&*CLIENT, post_id, CONFIG.allowed_authors.as_deref()).await
+}
+
+#[tracing::instrument(skip(client, allowed_authors))]
+async fn fetch_post_with<C: AsyncPostDataClient>(
+    client: &C,
+    post_id: &str,
+    allowed_authors: Option<&[String]>,
+) -> Result<PostResult, LocalError> {
     let time_start = Instant::now();
-    let post = CLIENT.get_post_data(post_id)?.get_post();
+    let result = client.get_post_data(post_id).await;
     let duration = time_start.elapsed();
-    println!("fetching {} took {}", post_id, duration.as_secs_f32());
-    Ok(html::html_page(
-        &post.title,
-        &post.render().unwrap().to_string(),
-    ))
+    tracing::info!(
+        post_id,
+        duration_secs = duration.as_secs_f32(),
+        outcome = if result.is_ok() { "ok" } else { "error" },
+        "fetched post"
+    );
+    let post = result?.get_post();
+    check_author(&post, allowed_authors)?;
+    Ok(post)
+}
+
+/// changes whenever medium's copy of the post does, or a deploy changes how we
+/// render it
+fn post_etag(post: &PostResult) -> String {
+    format!(
+        "\"{}-{}-{}\"",
+        post.id(),
+        post.updated_at(),
+        env!("CARGO_PKG_VERSION")
+    )
+}
+
+fn etag_matches(if_none_match: Option<&HeaderValue>, etag: &str) -> bool {
+    if_none_match
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| {
+            value
+                .split(',')
+                .map(str::trim)
+                .any(|tag| tag == "*" || tag.trim_start_matches("W/") == etag)
+        })
+}
+
+/// posts are rendered unless the client already has the current version
+fn post_response(
+    post: &PostResult,
+    if_none_match: Option<&HeaderValue>,
+    title_override: Option<&str>,
+    options: &RenderOptions,
+) -> Response<Body> {
+    let etag = post_etag(post);
+    let builder = Response::builder().header(ETAG, etag.as_str()).header(
+        CACHE_CONTROL,
+        HeaderValue::from_static("public, max-age=300"),
+    );
+
+    if etag_matches(if_none_match, &etag) {
+        return builder
+            .status(StatusCode::NOT_MODIFIED)
+            .body(Body::Empty)
+            .expect("failed to build response");
+    }
+
+    builder
+        .header(
+            CONTENT_TYPE,
+            HeaderValue::from_static("text/html; charset=utf-8"),
+        )
+        .status(StatusCode::OK)
+        .body(Body::Text(render_page(post, title_override, options)))
+        .expect("failed to build response")
+}
+
+fn html_response(status_code: StatusCode, content: String) -> Response<Body> {
+    Response::builder()
+        .header(
+            CONTENT_TYPE,
+            HeaderValue::from_static("text/html; charset=utf-8"),
+        )
+        .status(status_code)
+        .body(Body::Text(content))
+        .expect("failed to build response")
+}
+
+/// prefetches the post's images on the blocking pool, the response doesn't wait for it.
+/// on lambda this may be frozen together with the environment before it finishes
+fn spawn_image_prefetch(post: &PostResult, options: &RenderOptions) {
+    let post = post.clone();
+    let options = options.clone();
+    tokio::task::spawn_blocking(move || {
+        let Ok(content) = post.render_with(&options) else {
+            return;
+        };
+        let urls = prefetch::prefetch_images(&content);
+        tracing::debug!(post_id = post.id(), count = urls.len(), "prefetched images");
+    });
+}
+
+/// renders the full html page for a post. `title_override` only replaces the
+/// page title, the article itself is rendered unchanged.
+fn render_page(post: &PostResult, title_override: Option<&str>, options: &RenderOptions) -> String {
+    let mut page = Page::new(post)
+        .theme(CONFIG.theme.clone())
+        .stylesheet_href(CONFIG.link_stylesheet.then(stylesheet_href));
+    if let Some(title) = title_override {
+        page = page.title(title);
+    }
+    page.render_with(options).unwrap()
+}
+
+/// renders several posts below each other. a post that failed to load is shown
+/// as an error block in its slot instead of failing the whole page.
+fn render_series(
+    results: &[Result<PostResult, LocalError>],
+    title_override: Option<&str>,
+    options: &RenderOptions,
+) -> String {
+    let mut articles = Vec::with_capacity(results.len());
+    let mut page_options = PageOptions {
+        stylesheet_href: CONFIG.link_stylesheet.then(stylesheet_href),
+        theme: CONFIG.theme.clone(),
+        ..Default::default()
+    };
+    for result in results {
+        let article = match result.as_ref().map(|post| post.render_with(options)) {
+            Ok(Ok(content)) => {
+                page_options.math |= math::has_math(&content);
+                page_options.twitter_widget |= content.contains_class("twitter-tweet");
+                content.to_string()
+            }
+            Ok(Err(err)) => error_block(&err.to_string()),
+            Err(err) => error_block(&err.to_string()),
+        };
+        articles.push(article);
+    }
+
+    let title = match title_override {
+        Some(title) => title.to_string(),
+        None => results
+            .iter()
+            .filter_map(|result| result.as_ref().ok())
+            .map(|post| post.title.as_str())
+            .collect::<Vec<_>>()
+            .join(" | "),
+    };
+    html::html_page_with(&title, &articles.join("<hr/>"), &page_options)
+}
+
+fn error_block(message: &str) -> String {
+    maud::html! {
+        div class="post-error" role="alert" { (message) }
+    }
+    .into_string()
+}
+
+/// `id1,id2,id3` asks for several posts on one page, at most `MAX_SERIES_LEN`
+fn split_post_ids(path: &str) -> Option<Vec<String>> {
+    if !path.contains(',') {
+        return None;
+    }
+
+    let mut ids = parse_list(path);
+    ids.truncate(MAX_SERIES_LEN);
+    Some(ids)
+}
+
+/// fetches the whole series with one batched query, falling back to a request per
+/// post when the batch fails as a whole
+async fn fetch_series(paths: &[String]) -> Vec<Result<PostResult, LocalError>> {
+    let post_ids: Vec<String> = paths
+        .iter()
+        .filter_map(|path| client::extract_post_id(path))
+        .collect();
+    let batch = tokio::task::spawn_blocking(move || {
+        let post_ids: Vec<&str> = post_ids.iter().map(String::as_str).collect();
+        CLIENT.inner().get_posts(&post_ids)
+    })
+    .await
+    .expect("blocking fetch panicked");
+
+    match batch {
+        Ok(entries) => series_results(paths, entries, CONFIG.allowed_authors.as_deref()),
+        Err(err) => {
+            tracing::warn!(error = %err, "batched series fetch failed");
+            let mut results = Vec::with_capacity(paths.len());
+            for path in paths {
+                results.push(match client::extract_post_id(path) {
+                    Some(post_id) => fetch_post(&post_id).await,
+                    None => Err(client::ClientError::NotFoundError(path.clone()).into()),
+                });
+            }
+            results
+        }
+    }
+}
+
+/// pairs every path of a series with its entry of the batch, which holds one entry
+/// per path that carries a post id
+fn series_results(
+    paths: &[String],
+    entries: Vec<BatchEntry>,
+    allowed_authors: Option<&[String]>,
+) -> Vec<Result<PostResult, LocalError>> {
+    let mut entries = entries.into_iter();
+    paths
+        .iter()
+        .map(|path| {
+            let (_, result) = client::extract_post_id(path)
+                .and_then(|_| entries.next())
+                .ok_or_else(|| client::ClientError::NotFoundError(path.clone()))?;
+            let post = result?;
+            check_author(&post, allowed_authors)?;
+            Ok(post)
+        })
+        .collect()
 }
 
 fn map_error(res: Result<String, LocalError>) -> (StatusCode, String) {
+    if let Err(err) = &res {
+        tracing::error!(error = %err, "failed to serve post");
+    }
     match res {
         Ok(c) => (StatusCode::OK, c),
+        Err(LocalError::ClientError(err @ client::ClientError::GraphQlError(_))) => {
+            (StatusCode::BAD_GATEWAY, err.to_string())
+        }
+        Err(LocalError::ClientError(err @ client::ClientError::RateLimited { .. })) => {
+            (StatusCode::SERVICE_UNAVAILABLE, err.to_string())
+        }
         Err(LocalError::ClientError(err)) => (StatusCode::NOT_FOUND, err.to_string()),
-        Err(err) => (StatusCode::INTERNAL_SERVER_ERROR, err.to_string()),
+        Err(err @ LocalError::AuthorNotAllowed(_)) => (StatusCode::FORBIDDEN, err.to_string()),
     }
 }
 
-async fn handle_response_aws(event: Request) -> Result<impl IntoResponse, Error> {
-    let params = event.path_parameters();
-    let (status_code, content) = map_error(match params.first("postid") {
-        Some(postid) if postid.len() >= 1 => render_post(postid),
-        Some(_) => Ok(html::home()),
-        None => Ok(html::home()),
-    });
+const STYLESHEET_ROUTE: &str = "style.css";
+const HEALTH_ROUTE: &str = "healthz";
+const FAVICON_ROUTE: &str = "favicon.ico";
+/// every post is a separate fetch from medium, keep series pages bounded
+const MAX_SERIES_LEN: usize = 10;
+
+/// browsers ask for a favicon on every page, we have none
+fn favicon_response() -> Response<Body> {
+    Response::builder()
+        .header(
+            CACHE_CONTROL,
+            HeaderValue::from_static("public, max-age=86400"),
+        )
+        .status(StatusCode::NO_CONTENT)
+        .body(Body::Empty)
+        .expect("failed to build response")
+}
 
-    let builder = Response::builder()
+/// for load balancer probes, never touches medium
+fn health_response() -> Response<Body> {
+    Response::builder()
+        .header(CONTENT_TYPE, HeaderValue::from_static("application/json"))
+        .header(CACHE_CONTROL, HeaderValue::from_static("no-store"))
+        .status(StatusCode::OK)
+        .body(Body::Text(String::from(r#"{"status":"ok"}"#)))
+        .expect("failed to build response")
+}
+
+/// the stylesheet is cached for a long time, the version busts it on deploys
+fn stylesheet_href() -> String {
+    format!("/{}?v={}", STYLESHEET_ROUTE, env!("CARGO_PKG_VERSION"))
+}
+
+fn stylesheet_response() -> Response<Body> {
+    Response::builder()
         .header(
             CONTENT_TYPE,
-            HeaderValue::from_static("text/html; charset=utf-8"),
+            HeaderValue::from_static("text/css; charset=utf-8"),
         )
-        .status(status_code);
+        .header(
+            CACHE_CONTROL,
+            HeaderValue::from_static("public, max-age=31536000"),
+        )
+        .status(StatusCode::OK)
+        .body(Body::Text(CONFIG.theme.css().to_string()))
+        .expect("failed to build response")
+}
 
-    Ok(builder.body(content).expect("failed to build response"))
+fn image_response(res: Result<Image, client::ClientError>) -> Response<Body> {
+    match res {
+        Ok(image) => Response::builder()
+            .header(CONTENT_TYPE, image.content_type)
+            // image ids are content addressed, the bytes behind an url never change
+            .header(
+                CACHE_CONTROL,
+                HeaderValue::from_static("public, max-age=31536000, immutable"),
+            )
+            .status(StatusCode::OK)
+            .body(Body::Binary(image.data)),
+        Err(err) => Response::builder()
+            .status(StatusCode::NOT_FOUND)
+            .body(Body::Text(err.to_string())),
+    }
+    .expect("failed to build response")
+}
+
+/// compresses text bodies when the client accepts it
+fn compress_response(
+    response: Response<Body>,
+    accept_encoding: Option<&HeaderValue>,
+) -> Response<Body> {
+    let (mut parts, body) = response.into_parts();
+    let text = match body {
+        Body::Text(text) => text,
+        body => return Response::from_parts(parts, body),
+    };
+    parts
+        .headers
+        .insert(VARY, HeaderValue::from_static("Accept-Encoding"));
+
+    let encoding = accept_encoding
+        .and_then(|value| value.to_str().ok())
+        .and_then(compression::negotiate);
+    match encoding {
+        Some(encoding) if text.len() >= MIN_COMPRESSED_LEN => {
+            parts
+                .headers
+                .insert(CONTENT_ENCODING, HeaderValue::from_static(encoding.name()));
+            Response::from_parts(parts, Body::Binary(encoding.compress(text.as_bytes())))
+        }
+        _ => Response::from_parts(parts, Body::Text(text)),
+    }
+}
+
+async fn handle_response_aws(event: Request) -> Result<Response<Body>, Error> {
+    let accept_encoding = event.headers().get(ACCEPT_ENCODING).cloned();
+    let response = route(event).await?;
+    Ok(compress_response(response, accept_encoding.as_ref()))
+}
+
+async fn route(event: Request) -> Result<Response<Body>, Error> {
+    if let Some(ip) = request_client_ip(&event, &CONFIG.trusted_proxies) {
+        tracing::info!(client_ip = %ip, path = event.uri().path(), "request");
+    }
+
+    let params = event.path_parameters();
+    match params.first("postid") {
+        Some(STYLESHEET_ROUTE) => return Ok(stylesheet_response()),
+        Some(HEALTH_ROUTE) => return Ok(health_response()),
+        Some(FAVICON_ROUTE) => return Ok(favicon_response()),
+        _ => {}
+    }
+
+    if CONFIG.image_proxy {
+        if let Some(path) = params
+            .first("postid")
+            .and_then(|path| path.strip_prefix(IMAGE_PROXY_PREFIX))
+        {
+            let (width, image_id) = parse_proxied_image(path);
+            let image_id = image_id.to_string();
+            let image =
+                tokio::task::spawn_blocking(move || CLIENT.inner().get_image(&image_id, width))
+                    .await?;
+            return Ok(image_response(image));
+        }
+    }
+
+    let query = event.query_string_parameters();
+    let title_override = query.first("title").filter(|t| !t.is_empty());
+    let options = RenderOptions {
+        preview_limit: query.first("preview").and_then(|n| n.parse().ok()),
+        image_proxy: CONFIG.image_proxy,
+        twitter_widget: CONFIG.twitter_widget,
+        fallback_image: CONFIG.fallback_image.clone(),
+        image_width: CONFIG.image_width,
+        ..Default::default()
+    };
+    if let Some(paths) = params.first("postid").and_then(split_post_ids) {
+        let results = fetch_series(&paths).await;
+        return Ok(html_response(
+            StatusCode::OK,
+            render_series(&results, title_override, &options),
+        ));
+    }
+
+    let result = match params.first("postid") {
+        Some(postid) if !postid.is_empty() => match client::extract_post_id(postid) {
+            Some(post_id) => fetch_post(&post_id).await,
+            None => Err(client::ClientError::NotFoundError(postid.to_string()).into()),
+        },
+        _ => return Ok(html_response(StatusCode::OK, html::home())),
+    };
+
+    if let (Ok(post), true) = (&result, CONFIG.prefetch_images) {
+        spawn_image_prefetch(post, &options);
+    }
+
+    Ok(match result {
+        Ok(post) => post_response(
+            &post,
+            event.headers().get(IF_NONE_MATCH),
+            title_override,
+            &options,
+        ),
+        Err(err) => {
+            let (status_code, content) = map_error(Err(err));
+            html_response(status_code, content)
+        }
+    })
 }
 
 #[tokio::main]
 async fn main() -> Result<(), ()> {
+    lambda_http::tracing::init_default_subscriber();
     lambda_http::run(service_fn(handle_response_aws))
         .await
         .map_err(|_| ())?;
 
     Ok(())
 }
+
+#[cfg(test)]
+mod test {
+    use super::{
+        check_author, compress_response, fetch_post_with, image_response, map_error,
+        parse_cidr_list, parse_list, post_etag, post_response, render_page, render_series,
+        request_client_ip, route, series_results, split_post_ids, stylesheet_response, LocalError,
+    };
+    use crate::test_support::CapturingLayer;
+    use lambda_http::http::{
+        header::{CACHE_CONTROL, CONTENT_ENCODING, ETAG},
+        HeaderValue, StatusCode,
+    };
+    use lambda_http::request::from_str;
+    use lambda_http::Body;
+    use mediumrare::client::{AsyncPostDataClient, ClientError, Image, QueryResponse};
+    use mediumrare::content::RenderOptions;
+    use mediumrare::html::Theme;
+    use std::io::Read;
+    use std::time::Duration;
+    use tracing_subscriber::layer::SubscriberExt;
+
+    fn sample_post() -> mediumrare::client::PostResult {
+        serde_json::from_str::<QueryResponse>(include_str!("../test/post.json"))
+            .unwrap()
+            .get_post()
+    }
+
+    struct SamplePostClient;
+
+    impl AsyncPostDataClient for SamplePostClient {
+        async fn get_post_data(&self, _post_id: &str) -> Result<QueryResponse, ClientError> {
+            Ok(serde_json::from_str(include_str!("../test/post.json")).unwrap())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_fetch_logs_post_id() {
+        let layer = CapturingLayer::default();
+        let subscriber = tracing_subscriber::registry().with(layer.clone());
+        let _guard = tracing::subscriber::set_default(subscriber);
+
+        let post = fetch_post_with(&SamplePostClient, "a1b2c3d4e5f6", None)
+            .await
+            .unwrap();
+
+        assert_eq!("A Sample Post", post.title);
+        let fields = layer.fields.lock().unwrap();
+        assert!(fields.contains(&String::from("post_id=a1b2c3d4e5f6")));
+        assert!(fields.contains(&String::from("outcome=ok")));
+    }
+
+    #[test]
+    fn test_title_override_only_applies_to_page_title() {
+        let page = render_page(
+            &sample_post(),
+            Some("Custom <Title>"),
+            &RenderOptions::default(),
+        );
+
+        assert!(page.contains("<title>Custom &lt;Title&gt;</title>"));
+        assert!(page.contains("<h3>A Sample Post</h3>"));
+        assert!(!page.contains("<title>A Sample Post</title>"));
+    }
+
+    #[test]
+    fn test_page_title_defaults_to_post_title() {
+        let page = render_page(&sample_post(), None, &RenderOptions::default());
+
+        assert!(page.contains("<title>A Sample Post</title>"));
+        assert!(page.contains(
+            r#"<link rel="canonical" href="https://medium.com/@jdoe/a-sample-post-a1b2c3d4e5f6">"#
+        ));
+    }
+
+    #[test]
+    fn test_open_graph_image_uses_preview_image() {
+        let options = RenderOptions {
+            image_proxy: true,
+            ..Default::default()
+        };
+        let page = render_page(&sample_post(), None, &options);
+
+        assert!(page.contains(
+            r#"<meta property="og:image" content="https://miro.medium.com/v2/resize:fit:2000/1*preview.png">"#
+        ));
+        assert!(page.contains(r#"<meta property="og:title" content="A Sample Post">"#));
+    }
+
+    #[test]
+    fn test_series_renders_one_article_per_post() {
+        let results = vec![Ok(sample_post()), Ok(sample_post())];
+        let page = render_series(&results, None, &RenderOptions::default());
+
+        assert_eq!(2, page.matches("<article").count());
+        assert_eq!(1, page.matches("<hr/>").count());
+        assert!(page.contains("<title>A Sample Post | A Sample Post</title>"));
+    }
+
+    #[test]
+    fn test_series_shows_failed_posts_inline() {
+        let missing = ClientError::NotFoundError(String::from("ffffffffffff"));
+        let results = vec![Ok(sample_post()), Err(LocalError::from(missing))];
+        let page = render_series(&results, None, &RenderOptions::default());
+
+        assert_eq!(1, page.matches("<article").count());
+        assert!(page.contains(r#"<div class="post-error" role="alert">"#));
+        assert!(page.contains("ffffffffffff"));
+    }
+
+    #[test]
+    fn test_series_results_follow_path_order() {
+        let paths = vec![
+            String::from("a1b2c3d4e5f6"),
+            String::from("not-a-post"),
+            String::from("0123456789ab"),
+        ];
+        let entries = vec![
+            (String::from("a1b2c3d4e5f6"), Ok(sample_post())),
+            (
+                String::from("0123456789ab"),
+                Err(ClientError::NotFoundError(String::from("0123456789ab"))),
+            ),
+        ];
+
+        let results = series_results(&paths, entries, None);
+        assert_eq!(3, results.len());
+        assert_eq!("A Sample Post", results[0].as_ref().unwrap().title);
+        assert!(matches!(
+            &results[1],
+            Err(LocalError::ClientError(ClientError::NotFoundError(path))) if path == "not-a-post"
+        ));
+        assert!(matches!(
+            &results[2],
+            Err(LocalError::ClientError(ClientError::NotFoundError(_)))
+        ));
+    }
+
+    #[test]
+    fn test_series_results_check_authors() {
+        let paths = vec![String::from("a1b2c3d4e5f6")];
+        let entries = vec![(String::from("a1b2c3d4e5f6"), Ok(sample_post()))];
+        let allowed = vec![String::from("someone-else")];
+
+        let results = series_results(&paths, entries, Some(&allowed));
+        assert!(matches!(&results[0], Err(LocalError::AuthorNotAllowed(_))));
+    }
+
+    #[test]
+    fn test_split_post_ids() {
+        assert_eq!(None, split_post_ids("a1b2c3d4e5f6"));
+        assert_eq!(
+            Some(vec![
+                String::from("a1b2c3d4e5f6"),
+                String::from("ffffffffffff")
+            ]),
+            split_post_ids("a1b2c3d4e5f6, ffffffffffff,")
+        );
+    }
+
+    #[test]
+    fn test_image_proxy_sets_immutable_caching() {
+        let response = image_response(Ok(Image {
+            content_type: String::from("image/png"),
+            data: vec![1, 2, 3],
+        }));
+
+        assert_eq!(StatusCode::OK, response.status());
+        assert_eq!(
+            "public, max-age=31536000, immutable",
+            response.headers()[CACHE_CONTROL]
+        );
+        assert_eq!("image/png", response.headers()["content-type"]);
+        assert_eq!(&Body::Binary(vec![1, 2, 3]), response.body());
+    }
+
+    fn test_request(forwarded_for: &str) -> lambda_http::Request {
+        let mut event: serde_json::Value =
+            serde_json::from_str(include_str!("../test/request.json")).unwrap();
+        event["headers"]["X-Forwarded-For"] = forwarded_for.into();
+        from_str(&event.to_string()).unwrap()
+    }
+
+    fn path_request(path: &str) -> lambda_http::Request {
+        let mut event: serde_json::Value =
+            serde_json::from_str(include_str!("../test/request.json")).unwrap();
+        event["pathParameters"]["postid"] = path.into();
+        from_str(&event.to_string()).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_favicon_is_not_fetched_as_a_post() {
+        let response = route(path_request("favicon.ico")).await.unwrap();
+
+        assert_eq!(StatusCode::NO_CONTENT, response.status());
+        assert_eq!(&Body::Empty, response.body());
+    }
+
+    #[tokio::test]
+    async fn test_health_check_does_not_render_a_post() {
+        let response = route(path_request("healthz")).await.unwrap();
+
+        assert_eq!(StatusCode::OK, response.status());
+        assert_eq!("application/json", response.headers()["content-type"]);
+        assert_eq!(
+            &Body::Text(String::from(r#"{"status":"ok"}"#)),
+            response.body()
+        );
+    }
+
+    #[test]
+    fn test_client_ip_from_untrusted_peer() {
+        let request = test_request("1.2.3.4");
+        let trusted = parse_cidr_list("10.0.0.0/8");
+
+        assert_eq!(
+            Some("192.168.196.186".parse().unwrap()),
+            request_client_ip(&request, &trusted)
+        );
+    }
+
+    #[test]
+    fn test_client_ip_from_trusted_peer() {
+        let request = test_request("1.2.3.4");
+        let trusted = parse_cidr_list("192.168.0.0/16, not-a-cidr");
+
+        assert_eq!(1, trusted.len());
+        assert_eq!(
+            Some("1.2.3.4".parse().unwrap()),
+            request_client_ip(&request, &trusted)
+        );
+    }
+
+    #[test]
+    fn test_stylesheet_route_is_cacheable() {
+        let response = stylesheet_response();
+
+        assert_eq!(StatusCode::OK, response.status());
+        assert_eq!(
+            "public, max-age=31536000",
+            response.headers()[CACHE_CONTROL]
+        );
+        assert_eq!(
+            "text/css; charset=utf-8",
+            response.headers()["content-type"]
+        );
+        assert_eq!(
+            &Body::Text(Theme::default().css().to_string()),
+            response.body()
+        );
+    }
+
+    #[test]
+    fn test_allowlisted_author_is_rendered() {
+        let allowed = parse_list("someone, jdoe");
+
+        assert!(check_author(&sample_post(), Some(&allowed)).is_ok());
+        assert!(check_author(&sample_post(), None).is_ok());
+    }
+
+    #[test]
+    fn test_other_authors_are_forbidden() {
+        let allowed = parse_list("someone");
+        let result = check_author(&sample_post(), Some(&allowed)).map(|_| String::new());
+
+        assert_eq!(StatusCode::FORBIDDEN, map_error(result).0);
+    }
+
+    #[test]
+    fn test_graphql_errors_are_shown_as_bad_gateway() {
+        let error = ClientError::GraphQlError(vec![String::from("rate limited")]);
+        let (status, body) = map_error(Err(error.into()));
+        assert_eq!(StatusCode::BAD_GATEWAY, status);
+        assert!(body.contains("rate limited"));
+    }
+
+    #[test]
+    fn test_rate_limited_fetches_are_unavailable() {
+        let error = ClientError::RateLimited {
+            retry_after: Duration::from_secs(1),
+        };
+        assert_eq!(
+            StatusCode::SERVICE_UNAVAILABLE,
+            map_error(Err(error.into())).0
+        );
+    }
+
+    #[test]
+    fn test_matching_etag_is_not_modified() {
+        let post = sample_post();
+        let etag = HeaderValue::from_str(&post_etag(&post)).unwrap();
+
+        let response = post_response(&post, Some(&etag), None, &RenderOptions::default());
+        assert_eq!(StatusCode::NOT_MODIFIED, response.status());
+        assert_eq!(&Body::Empty, response.body());
+        assert_eq!(etag, response.headers()[ETAG]);
+    }
+
+    #[test]
+    fn test_stale_etag_renders_post() {
+        let post = sample_post();
+        let stale = HeaderValue::from_static("\"a1b2c3d4e5f6-1-0.1.0\"");
+
+        let response = post_response(&post, Some(&stale), None, &RenderOptions::default());
+        assert_eq!(StatusCode::OK, response.status());
+        assert_eq!(post_etag(&post), response.headers()[ETAG]);
+        assert_eq!("public, max-age=300", response.headers()[CACHE_CONTROL]);
+        assert!(matches!(response.body(), Body::Text(html) if html.contains("A Sample Post")));
+    }
+
+    #[test]
+    fn test_gzip_is_negotiated_for_large_pages() {
+        let post = sample_post();
+        let response = post_response(&post, None, None, &RenderOptions::default());
+        let Body::Text(html) = response.body().clone() else {
+            panic!("post pages are text");
+        };
+
+        let accept = HeaderValue::from_static("gzip, deflate");
+        let response = compress_response(response, Some(&accept));
+        assert_eq!("gzip", response.headers()[CONTENT_ENCODING]);
+        let Body::Binary(compressed) = response.body() else {
+            panic!("compressed bodies are binary");
+        };
+        let mut decompressed = String::new();
+        flate2::read::GzDecoder::new(compressed.as_slice())
+            .read_to_string(&mut decompressed)
+            .unwrap();
+        assert_eq!(html, decompressed);
+    }
+
+    #[test]
+    fn test_small_responses_stay_uncompressed() {
+        let response = lambda_http::Response::new(Body::Text(String::from("not found")));
+        let accept = HeaderValue::from_static("gzip");
+
+        let response = compress_response(response, Some(&accept));
+        assert!(!response.headers().contains_key(CONTENT_ENCODING));
+        assert_eq!(&Body::Text(String::from("not found")), response.body());
+    }
+}
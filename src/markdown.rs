@@ -0,0 +1,288 @@
+//! converts a rendered `Content` tree into markdown, e.g. for importing posts into
+//! static site generators
+
+use crate::client::PostResult;
+use crate::content::{unescape_text, Content};
+
+fn inline_children(children: &Option<Vec<Content>>) -> String {
+    children
+        .iter()
+        .flatten()
+        .map(inline)
+        .collect::<Vec<String>>()
+        .concat()
+}
+
+fn inline(content: &Content) -> String {
+    match content {
+        Content::Text(text) => unescape_text(text),
+        Content::Tag {
+            name,
+            attributes,
+            children,
+        } => {
+            let inner = inline_children(children);
+            let attr = |name: &str| attributes.get(name).map_or("", String::as_str);
+            match name.as_str() {
+                "strong" => format!("**{}**", inner),
+                "em" => format!("_{}_", inner),
+                "code" => format!("`{}`", inner),
+                "del" => format!("~~{}~~", inner),
+                "a" => format!("[{}]({})", inner, attr("href")),
+                "img" => format!("![{}]({})", attr("alt"), attr("src")),
+                "br" => String::from("  \n"),
+                _ => inner,
+            }
+        }
+    }
+}
+
+fn block(content: &Content, out: &mut String) {
+    let Content::Tag { name, children, .. } = content else {
+        out.push_str(&inline(content));
+        return;
+    };
+
+    match name.as_str() {
+        "h1" | "h2" | "h3" | "h4" | "h5" | "h6" => {
+            let level = name[1..].parse().unwrap_or(1);
+            out.push_str(&format!(
+                "{} {}\n\n",
+                "#".repeat(level),
+                inline_children(children)
+            ));
+        }
+        "p" | "div" | "figcaption" => out.push_str(&format!("{}\n\n", inline_children(children))),
+        "img" => out.push_str(&format!("{}\n\n", inline(content))),
+        "hr" => out.push_str("---\n\n"),
+        "ul" | "ol" => {
+            for (index, item) in children.iter().flatten().enumerate() {
+                let marker = match name.as_str() {
+                    "ol" => format!("{}.", index + 1),
+                    _ => String::from("-"),
+                };
+                out.push_str(&format!("{} {}\n", marker, inline(item)));
+            }
+            out.push('\n');
+        }
+        "li" => out.push_str(&format!("- {}\n", inline_children(children))),
+        "pre" => {
+            // the code is wrapped in a `code` tag carrying its language
+            let (lang, code) = match children.as_deref() {
+                Some(
+                    [Content::Tag {
+                        name,
+                        attributes,
+                        children,
+                    }],
+                ) if name == "code" => (
+                    attributes
+                        .get("class")
+                        .and_then(|class| class.strip_prefix("language-"))
+                        .unwrap_or(""),
+                    inline_children(children),
+                ),
+                _ => ("", inline_children(children)),
+            };
+            out.push_str(&format!("```{}\n{}\n```\n\n", lang, code));
+        }
+        "blockquote" => {
+            let quoted: Vec<String> = inline_children(children)
+                .lines()
+                .map(|line| format!("> {}", line))
+                .collect();
+            out.push_str(&format!("{}\n\n", quoted.join("\n")));
+        }
+        _ => children
+            .iter()
+            .flatten()
+            .for_each(|child| block(child, out)),
+    }
+}
+
+pub fn to_markdown(content: &Content) -> String {
+    let mut out = String::new();
+    block(content, &mut out);
+    format!("{}\n", out.trim_end())
+}
+
+/// json strings are valid double quoted yaml scalars, which gives us a correct
+/// escape for free
+fn yaml_string(value: &str) -> String {
+    serde_json::to_string(value).expect("strings always serialize")
+}
+
+/// the yaml front matter static site generators expect in front of the markdown
+pub fn front_matter(post: &PostResult) -> String {
+    let mut out = String::from("---\n");
+    out.push_str(&format!("title: {}\n", yaml_string(&post.title)));
+    out.push_str(&format!("author: {}\n", yaml_string(&post.creator.name)));
+    out.push_str(&format!(
+        "date: {}\n",
+        yaml_string(&post.published_time().to_rfc3339())
+    ));
+    out.push_str(&format!(
+        "canonical_url: {}\n",
+        yaml_string(&post.medium_url)
+    ));
+    out.push_str("tags:\n");
+    for tag in &post.tags {
+        out.push_str(&format!("  - {}\n", yaml_string(&tag.display_title)));
+    }
+    out.push_str("---\n");
+    out
+}
+
+pub fn to_markdown_with_front_matter(post: &PostResult, content: &Content) -> String {
+    format!("{}\n{}", front_matter(post), to_markdown(content))
+}
+
+#[cfg(test)]
+mod test {
+    use crate::client::{PostResult, QueryResponse};
+    use crate::content::Content;
+    use crate::content::Render;
+    use crate::markdown::{front_matter, to_markdown, to_markdown_with_front_matter};
+    use serde_yaml::Value;
+    use std::collections::BTreeMap;
+
+    fn sample_post() -> PostResult {
+        serde_json::from_str::<QueryResponse>(include_str!("../test/post.json"))
+            .unwrap()
+            .get_post()
+    }
+
+    fn tag(name: &str, children: Vec<Content>) -> Content {
+        Content::tag(name, None, Some(children))
+    }
+
+    fn text(text: &str) -> Content {
+        Content::text(text)
+    }
+
+    #[test]
+    fn test_headings() {
+        let article = tag(
+            "article",
+            vec![tag("h1", vec![text("Title")]), tag("h4", vec![text("Sub")])],
+        );
+        assert_eq!("# Title\n\n#### Sub\n", to_markdown(&article));
+    }
+
+    #[test]
+    fn test_inline_markup() {
+        let paragraph = tag(
+            "p",
+            vec![
+                tag("strong", vec![text("bold "), tag("em", vec![text("both")])]),
+                text(", "),
+                tag("code", vec![text("a < b")]),
+                text(" and "),
+                Content::hyperlink("https://example.com", vec![text("a link")], None),
+            ],
+        );
+        assert_eq!(
+            "**bold _both_**, `a < b` and [a link](https://example.com)\n",
+            to_markdown(&paragraph)
+        );
+    }
+
+    #[test]
+    fn test_blockquote_prefixes_every_line() {
+        let quote = tag("blockquote", vec![text("one\ntwo")]);
+        assert_eq!("> one\n> two\n", to_markdown(&quote));
+    }
+
+    #[test]
+    fn test_image() {
+        let attributes = BTreeMap::from([
+            (
+                String::from("src"),
+                String::from("https://example.com/a.png"),
+            ),
+            (String::from("alt"), String::from("a cat")),
+        ]);
+        let image = Content::tag("img", Some(attributes), None);
+        assert_eq!("![a cat](https://example.com/a.png)\n", to_markdown(&image));
+    }
+
+    #[test]
+    fn test_lists_are_separated_from_following_paragraphs() {
+        let article = tag(
+            "article",
+            vec![
+                tag(
+                    "ul",
+                    vec![tag("li", vec![text("a")]), tag("li", vec![text("b")])],
+                ),
+                tag(
+                    "ol",
+                    vec![tag("li", vec![text("one")]), tag("li", vec![text("two")])],
+                ),
+                tag("p", vec![text("after")]),
+            ],
+        );
+        assert_eq!(
+            "- a\n- b\n\n1. one\n2. two\n\nafter\n",
+            to_markdown(&article)
+        );
+    }
+
+    #[test]
+    fn test_preformatted() {
+        let code = tag("pre", vec![text("let x = 1;")]);
+        assert_eq!("```\nlet x = 1;\n```\n", to_markdown(&code));
+    }
+
+    #[test]
+    fn test_preformatted_code_keeps_language() {
+        let code = tag(
+            "pre",
+            vec![Content::tag_with_class(
+                "code",
+                "language-rust",
+                Some(vec![text("let x = 1;")]),
+            )],
+        );
+        assert_eq!("```rust\nlet x = 1;\n```\n", to_markdown(&code));
+    }
+
+    #[test]
+    fn test_front_matter_is_valid_yaml() {
+        let mut post = sample_post();
+        post.title = String::from("Quotes \" and: colons\n# not a comment");
+
+        let front_matter = front_matter(&post);
+        let yaml = front_matter
+            .strip_prefix("---\n")
+            .and_then(|s| s.strip_suffix("---\n"))
+            .unwrap();
+        let parsed: Value = serde_yaml::from_str(yaml).unwrap();
+
+        assert_eq!(
+            "Quotes \" and: colons\n# not a comment",
+            parsed["title"].as_str().unwrap()
+        );
+        assert_eq!("Jane Doe", parsed["author"].as_str().unwrap());
+        assert_eq!("2020-05-15T06:01:09Z", parsed["date"].as_str().unwrap());
+        assert_eq!(
+            "https://medium.com/@jdoe/a-sample-post-a1b2c3d4e5f6",
+            parsed["canonical_url"].as_str().unwrap()
+        );
+        assert_eq!("Rust", parsed["tags"][0].as_str().unwrap());
+    }
+
+    #[test]
+    fn test_markdown_body_follows_front_matter() {
+        let post = sample_post();
+        let markdown = to_markdown_with_front_matter(&post, &post.render().unwrap());
+
+        assert!(markdown.starts_with("---\ntitle: \"A Sample Post\"\n"));
+        assert!(markdown.contains(concat!(
+            "---\n\n![](https://miro.medium.com/v2/resize:fit:2000/1*preview.png)\n\n",
+            "published by [Jane Doe](https://medium.com/@jdoe)"
+        )));
+        assert!(markdown.contains("### A Sample Post\n\nThis is the **first** paragraph.\n\n"));
+        assert!(markdown.contains("![](https://miro.medium.com/v2/resize:fit:2000/1*image.png)"));
+    }
+}
@@ -0,0 +1,162 @@
+//! detection of `$...$` and `$$...$$` delimited math, rendered client side by katex.
+//! the conversion is only applied to paragraphs when the `math` feature is enabled.
+
+use crate::content::Content;
+
+pub const KATEX_VERSION: &str = "0.16.9";
+
+/// splits `$...$` delimited inline math out of the text nodes into `<span class="math">`
+pub fn render_inline_math(children: Vec<Content>) -> Vec<Content> {
+    children
+        .into_iter()
+        .flat_map(|child| match child {
+            Content::Text(text) => split_inline_math(&text),
+            Content::Tag {
+                name,
+                attributes,
+                children,
+            } => vec![Content::Tag {
+                name,
+                attributes,
+                children: children.map(render_inline_math),
+            }],
+        })
+        .collect()
+}
+
+/// returns the inner formula if the whole paragraph is a `$$...$$` block
+pub fn block_math(text: &str) -> Option<&str> {
+    let formula = text.trim().strip_prefix("$$")?.strip_suffix("$$")?;
+    (!formula.trim().is_empty()).then_some(formula)
+}
+
+pub fn math_block(formula: &str) -> Content {
+    Content::tag_with_class(
+        "div",
+        "math-block",
+        Some(vec![Content::text(format!("\\[{}\\]", formula))]),
+    )
+}
+
+/// the text nodes are already escaped, so they are put back as raw text
+fn split_inline_math(text: &str) -> Vec<Content> {
+    let mut parts = Vec::new();
+    let mut rest = text;
+
+    while let Some((before, formula, after)) = next_inline_formula(rest) {
+        if !before.is_empty() {
+            parts.push(Content::Text(before.to_string()));
+        }
+        parts.push(Content::tag_with_class(
+            "span",
+            "math",
+            Some(vec![Content::Text(format!("\\({}\\)", formula))]),
+        ));
+        rest = after;
+    }
+
+    if !rest.is_empty() || parts.is_empty() {
+        parts.push(Content::Text(rest.to_string()));
+    }
+
+    parts
+}
+
+/// a formula starts with a `$` not followed by whitespace and ends with a `$` not
+/// preceded by whitespace, this keeps prices like "$5 and $10" as plain text
+fn next_inline_formula(text: &str) -> Option<(&str, &str, &str)> {
+    let mut search_from = 0;
+    loop {
+        let open = search_from + text[search_from..].find('$')?;
+        let inner_start = open + 1;
+        let close = inner_start + text[inner_start..].find('$')?;
+        let formula = &text[inner_start..close];
+
+        let starts_clean = formula.chars().next().is_some_and(|c| !c.is_whitespace());
+        let ends_clean = formula.chars().last().is_some_and(|c| !c.is_whitespace());
+        if starts_clean && ends_clean {
+            return Some((&text[..open], formula, &text[close + 1..]));
+        }
+
+        search_from = close;
+    }
+}
+
+/// whether any math markup ended up in the rendered tree
+pub fn has_math(content: &Content) -> bool {
+    content.contains_class("math") || content.contains_class("math-block")
+}
+
+#[cfg(test)]
+mod test {
+    use crate::content::Content;
+    use crate::math::{block_math, has_math, render_inline_math};
+    #[cfg(feature = "math")]
+    use crate::{client::Paragraph, content::Render};
+
+    #[cfg(feature = "math")]
+    fn paragraph(text: &str) -> Paragraph {
+        Paragraph {
+            id: String::from(""),
+            href: None,
+            layout: None,
+            text: Some(String::from(text)),
+            r#type: "P".into(),
+            markups: vec![],
+            metadata: None,
+            iframe: None,
+            code_block_metadata: None,
+            extra: Default::default(),
+        }
+    }
+
+    #[cfg(feature = "math")]
+    #[test]
+    fn test_inline_math() {
+        let rendered = paragraph("so $a^2 + b^2$ holds").render().unwrap();
+
+        assert_eq!(
+            r#"<p>so <span class="math">\(a^2 + b^2\)</span> holds</p>"#,
+            rendered.to_string()
+        );
+        assert!(has_math(&rendered));
+    }
+
+    #[cfg(feature = "math")]
+    #[test]
+    fn test_block_math() {
+        let rendered = paragraph("$$E = mc^2$$").render().unwrap();
+
+        assert_eq!(
+            r#"<div class="math-block">\[E = mc^2\]</div>"#,
+            rendered.to_string()
+        );
+        assert!(has_math(&rendered));
+    }
+
+    #[cfg(feature = "math")]
+    #[test]
+    fn test_prices_are_not_math() {
+        let rendered = paragraph("it costs $5 and $10").render().unwrap();
+
+        assert_eq!("<p>it costs $5 and $10</p>", rendered.to_string());
+        assert!(!has_math(&rendered));
+    }
+
+    #[test]
+    fn test_nested_text_is_split() {
+        let children = vec![Content::tag(
+            "strong",
+            None,
+            Some(vec![Content::text("$x$")]),
+        )];
+
+        let rendered = render_inline_math(children);
+        assert_eq!(
+            r#"<strong><span class="math">\(x\)</span></strong>"#,
+            rendered[0].to_string()
+        );
+        assert!(has_math(&rendered[0]));
+        assert_eq!(None, block_math("$$ $$"));
+    }
+}
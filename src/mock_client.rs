@@ -1,22 +1,52 @@
-use crate::client::QueryResponse;
-use crate::PostDataClient;
+use crate::client::{ClientError, PostDataClient, QueryResponse};
 use std::cell::RefCell;
 use std::collections::HashMap;
 
 pub struct MockClient {
-    response_map: RefCell<HashMap<String, Result<QueryResponse, ()>>>,
+    response_map: RefCell<HashMap<String, QueryResponse>>,
 }
 
 impl MockClient {
-    fn set_mock_post_data<T: Into<String>>(&self, post_id: T, result: Result<QueryResponse, ()>) {
+    pub fn new() -> MockClient {
+        MockClient {
+            response_map: RefCell::new(HashMap::new()),
+        }
+    }
+
+    pub fn set_mock_post_data<T: Into<String>>(&self, post_id: T, response: QueryResponse) {
         self.response_map
             .borrow_mut()
-            .insert(post_id.into(), result);
+            .insert(post_id.into(), response);
     }
 }
 
 impl PostDataClient for MockClient {
-    fn get_post_data(&self, post_id: &str) -> Result<QueryResponse, ()> {
-        self.response_map.borrow()[post_id].clone()
+    fn get_post_data(&self, post_id: &str) -> Result<QueryResponse, ClientError> {
+        self.response_map
+            .borrow()
+            .get(post_id)
+            .cloned()
+            .ok_or_else(|| ClientError::NotFoundError(post_id.to_string()))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::client::{ClientError, PostDataClient, QueryResponse};
+    use crate::mock_client::MockClient;
+
+    #[test]
+    fn test_returns_registered_posts_only() {
+        let client = MockClient::new();
+        let response: QueryResponse =
+            serde_json::from_str(include_str!("../test/post.json")).unwrap();
+        client.set_mock_post_data("a1b2c3d4e5f6", response);
+
+        let post = client.get_post_data("a1b2c3d4e5f6").unwrap().get_post();
+        assert_eq!("A Sample Post", post.title);
+        assert!(matches!(
+            client.get_post_data("ffffffffffff"),
+            Err(ClientError::NotFoundError(id)) if id == "ffffffffffff"
+        ));
     }
 }
@@ -0,0 +1,170 @@
+//! warms medium's image cdn, so the images of a freshly rendered post are cached
+//! by the time readers' browsers ask for them
+
+use crate::content::Content;
+use std::sync::Mutex;
+use std::thread;
+use std::time::Duration;
+
+const CDN_PREFIX: &str = "https://miro.medium.com/";
+const PREFETCH_TIMEOUT: Duration = Duration::from_secs(5);
+/// how many `HEAD` requests are in flight at once, however many images a post has
+const PREFETCH_WORKERS: usize = 4;
+
+fn collect_image_urls(content: &Content, urls: &mut Vec<String>) {
+    let Content::Tag {
+        name,
+        attributes,
+        children,
+    } = content
+    else {
+        return;
+    };
+
+    if name == "img" {
+        if let Some(src) = attributes.get("src") {
+            if src.starts_with(CDN_PREFIX) && !urls.contains(src) {
+                urls.push(src.clone());
+            }
+        }
+    }
+    for child in children.iter().flatten() {
+        collect_image_urls(child, urls);
+    }
+}
+
+/// the medium cdn urls of all `<img>`s in `content`, each once, in document order.
+/// proxied images and images hosted elsewhere are left out
+pub fn image_urls(content: &Content) -> Vec<String> {
+    let mut urls = Vec::new();
+    collect_image_urls(content, &mut urls);
+    urls
+}
+
+/// runs `fetch` over `urls` on at most [`PREFETCH_WORKERS`] threads and keeps the
+/// urls it succeeded for, in their original order
+fn fetch_all(urls: Vec<String>, fetch: impl Fn(&str) -> bool + Sync) -> Vec<String> {
+    let workers = urls.len().min(PREFETCH_WORKERS);
+    let queue = Mutex::new(urls.into_iter().enumerate());
+
+    let mut fetched: Vec<(usize, String)> = thread::scope(|scope| {
+        let handles: Vec<_> = (0..workers)
+            .map(|_| {
+                scope.spawn(|| {
+                    let mut fetched = Vec::new();
+                    loop {
+                        let next = queue.lock().unwrap_or_else(|e| e.into_inner()).next();
+                        let Some((index, url)) = next else {
+                            break fetched;
+                        };
+                        if fetch(&url) {
+                            fetched.push((index, url));
+                        }
+                    }
+                })
+            })
+            .collect();
+
+        handles
+            .into_iter()
+            .filter_map(|handle| handle.join().ok())
+            .flatten()
+            .collect()
+    });
+
+    fetched.sort_unstable_by_key(|(index, _)| *index);
+    fetched.into_iter().map(|(_, url)| url).collect()
+}
+
+/// sends a `HEAD` request for every image of `content`, a few at a time, and
+/// waits for all of them. returns the urls the cdn answered successfully.
+///
+/// this blocks until every request finished or timed out, so callers that
+/// return before it's done (like the lambda handler) may get frozen mid-way
+pub fn prefetch_images(content: &Content) -> Vec<String> {
+    let agent: ureq::Agent = ureq::Agent::config_builder()
+        .timeout_global(Some(PREFETCH_TIMEOUT))
+        .build()
+        .into();
+
+    fetch_all(image_urls(content), |url| agent.head(url).call().is_ok())
+}
+
+#[cfg(test)]
+mod test {
+    use crate::content::Content;
+    use crate::prefetch::{fetch_all, image_urls, PREFETCH_WORKERS};
+    use std::collections::BTreeMap;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::thread;
+    use std::time::Duration;
+
+    fn image(src: &str) -> Content {
+        Content::tag(
+            "img",
+            Some(BTreeMap::from([(String::from("src"), String::from(src))])),
+            None,
+        )
+    }
+
+    #[test]
+    fn test_image_urls_are_collected_from_the_tree() {
+        let article = Content::tag(
+            "article",
+            None,
+            Some(vec![
+                image("https://miro.medium.com/v2/resize:fit:2000/1*hero.png"),
+                Content::tag(
+                    "figure",
+                    None,
+                    Some(vec![
+                        image("https://miro.medium.com/v2/resize:fit:2000/1*figure.png"),
+                        Content::tag("figcaption", None, Some(vec![Content::text("a cat")])),
+                    ]),
+                ),
+                image("https://miro.medium.com/v2/resize:fit:2000/1*hero.png"),
+                image("/img/2000/1*proxied.png"),
+                image("https://example.com/elsewhere.png"),
+            ]),
+        );
+
+        assert_eq!(
+            vec![
+                "https://miro.medium.com/v2/resize:fit:2000/1*hero.png",
+                "https://miro.medium.com/v2/resize:fit:2000/1*figure.png",
+            ],
+            image_urls(&article)
+        );
+    }
+
+    #[test]
+    fn test_fetches_are_bounded_by_the_worker_pool() {
+        let urls: Vec<String> = (0..20)
+            .map(|i| format!("https://miro.medium.com/{i}"))
+            .collect();
+        let running = AtomicUsize::new(0);
+        let peak = AtomicUsize::new(0);
+
+        let fetched = fetch_all(urls.clone(), |url| {
+            let now = running.fetch_add(1, Ordering::SeqCst) + 1;
+            peak.fetch_max(now, Ordering::SeqCst);
+            thread::sleep(Duration::from_millis(5));
+            running.fetch_sub(1, Ordering::SeqCst);
+            !url.ends_with('7')
+        });
+
+        assert!(peak.load(Ordering::SeqCst) <= PREFETCH_WORKERS);
+        let expected: Vec<String> = urls.into_iter().filter(|url| !url.ends_with('7')).collect();
+        assert_eq!(expected, fetched);
+    }
+
+    #[test]
+    fn test_nothing_to_fetch() {
+        assert!(fetch_all(Vec::new(), |_| unreachable!()).is_empty());
+    }
+
+    #[test]
+    fn test_text_has_no_images() {
+        assert!(image_urls(&Content::text("<img src=\"x\">")).is_empty());
+    }
+}
@@ -0,0 +1,36 @@
+//! helpers shared by the library and the lambda binary tests. the binary includes
+//! this file as its own module, since it can't see the library's test code
+
+use std::sync::{Arc, Mutex};
+use tracing::field::{Field, Visit};
+use tracing::span::Attributes;
+use tracing::{Event, Id, Subscriber};
+use tracing_subscriber::layer::{Context, Layer};
+
+/// records the fields of every span and event as `name=value`
+#[derive(Clone, Default)]
+pub struct CapturingLayer {
+    pub fields: Arc<Mutex<Vec<String>>>,
+}
+
+impl Visit for CapturingLayer {
+    fn record_str(&mut self, field: &Field, value: &str) {
+        let entry = format!("{}={}", field.name(), value);
+        self.fields.lock().unwrap().push(entry);
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
+        let entry = format!("{}={:?}", field.name(), value);
+        self.fields.lock().unwrap().push(entry);
+    }
+}
+
+impl<S: Subscriber> Layer<S> for CapturingLayer {
+    fn on_new_span(&self, attrs: &Attributes<'_>, _id: &Id, _ctx: Context<'_, S>) {
+        attrs.record(&mut self.clone());
+    }
+
+    fn on_event(&self, event: &Event<'_>, _ctx: Context<'_, S>) {
+        event.record(&mut self.clone());
+    }
+}
@@ -1,6 +1,6 @@
 use crate::content::Content;
 use anyhow::Result;
-use std::collections::HashMap;
+use std::collections::BTreeMap;
 
 #[derive(Debug, thiserror::Error)]
 pub enum RenderingError {
@@ -14,11 +14,15 @@ enum SpanContent<'a> {
     Spans(Vec<TextSpan<'a>>),
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum SpanWrap {
     Strong,
     Emphasized,
+    Strikethrough,
+    Superscript,
+    Subscript,
     Link { href: String },
+    Mention { user_id: String },
     Unknown { tag: String },
     Code,
 }
@@ -32,17 +36,37 @@ pub struct TextSpan<'a> {
 }
 
 impl SpanWrap {
+    /// wraps with a lower rank are nested further inside: links outermost, then
+    /// strong and em, code innermost
+    fn nesting_rank(&self) -> u8 {
+        match self {
+            SpanWrap::Code => 0,
+            SpanWrap::Unknown { .. } => 1,
+            SpanWrap::Strikethrough => 2,
+            SpanWrap::Superscript | SpanWrap::Subscript => 3,
+            SpanWrap::Emphasized => 4,
+            SpanWrap::Strong => 5,
+            SpanWrap::Link { .. } | SpanWrap::Mention { .. } => 6,
+        }
+    }
+
     fn create_tag(&self, children: Vec<Content>) -> Content {
-        let empty = HashMap::new();
+        let empty = BTreeMap::new();
         let (tag_name, attributes) = match self {
             SpanWrap::Strong => ("strong", empty),
             SpanWrap::Emphasized => ("em", empty),
             SpanWrap::Code => ("code", empty),
+            SpanWrap::Strikethrough => ("del", empty),
+            SpanWrap::Superscript => ("sup", empty),
+            SpanWrap::Subscript => ("sub", empty),
             SpanWrap::Unknown { tag } => (tag.as_str(), empty),
-            SpanWrap::Link { href } => {
-                let mut attributes = HashMap::new();
-                attributes.insert("href".into(), href.to_owned());
-                ("a", attributes)
+            SpanWrap::Link { href } => return Content::hyperlink(href, children, None),
+            SpanWrap::Mention { user_id } => {
+                return Content::hyperlink(
+                    format!("https://medium.com/u/{}", user_id),
+                    children,
+                    None,
+                )
             }
         };
 
@@ -54,9 +78,9 @@ impl SpanWrap {
     }
 }
 
-impl<'a> Into<Vec<Content>> for TextSpan<'a> {
-    fn into(self) -> Vec<Content> {
-        let inner = match self.content {
+impl<'a> From<TextSpan<'a>> for Vec<Content> {
+    fn from(span: TextSpan<'a>) -> Vec<Content> {
+        let inner = match span.content {
             SpanContent::Text(str) => vec![Content::text(str)],
             SpanContent::Spans(spans) => spans
                 .into_iter()
@@ -64,12 +88,16 @@ impl<'a> Into<Vec<Content>> for TextSpan<'a> {
                 .collect(),
         };
 
-        if self.wraps.is_empty() {
+        if span.wraps.is_empty() {
             return inner;
         }
 
+        // markups arrive in whatever order medium lists them, nest them canonically
+        let mut wraps = span.wraps;
+        wraps.sort_by_key(SpanWrap::nesting_rank);
+
         let mut wrapped = inner;
-        for wrapper in self.wraps {
+        for wrapper in wraps {
             wrapped = vec![wrapper.create_tag(wrapped)];
         }
 
@@ -78,10 +106,12 @@ impl<'a> Into<Vec<Content>> for TextSpan<'a> {
 }
 
 impl<'a> TextSpan<'a> {
+    /// the span over all of `content`. empty paragraphs exist, their span has
+    /// nothing to split and renders as empty text
     pub fn create(content: &'a str) -> TextSpan<'a> {
         TextSpan {
             start: 0,
-            end: utf16_len(content) - 1,
+            end: utf16_len(content).saturating_sub(1),
             content: SpanContent::Text(content),
             wraps: Vec::new(),
         }
@@ -100,6 +130,35 @@ impl<'a> TextSpan<'a> {
         self.wraps.push(wrap);
     }
 
+    /// wraps the text between `start` and `end`, even when the range crosses the
+    /// bounds of spans split off for earlier markups. every span it only partly covers
+    /// is split again and its covered part wrapped on its own.
+    pub fn wrap_range(&mut self, start: usize, end: usize, wrap: &SpanWrap) {
+        let start = start.max(self.start);
+        let end = end.min(self.end);
+        if start > end {
+            return;
+        }
+        if start == self.start && end == self.end {
+            self.add_wrap(wrap.clone());
+            return;
+        }
+
+        if let SpanContent::Spans(spans) = &mut self.content {
+            for span in spans
+                .iter_mut()
+                .filter(|span| span.start <= end && span.end >= start)
+            {
+                span.wrap_range(start, end, wrap);
+            }
+            return;
+        }
+
+        self.get_sub_span_mut(start, end)
+            .expect("text spans can always be split")
+            .add_wrap(wrap.clone());
+    }
+
     pub fn get_sub_span_mut(&mut self, start: usize, end: usize) -> Result<&mut TextSpan<'a>> {
         debug_assert!(end >= start);
         // sometime they send us offsets outside the actual string.. thanks
@@ -110,6 +169,10 @@ impl<'a> TextSpan<'a> {
 
         match self.content {
             SpanContent::Text(str_content) => {
+                let (start, end) = snap_to_chars(str_content, self.start, start, end);
+                if start == self.start && end == self.end {
+                    return Ok(self);
+                }
                 let (new_content, idx) = Self::split_str(str_content, self.start, start, end);
                 self.content = SpanContent::Spans(new_content);
                 if let SpanContent::Spans(ref mut spans) = &mut self.content {
@@ -130,7 +193,12 @@ impl<'a> TextSpan<'a> {
         }
     }
 
-    fn split_str(content: &str, offset: usize, start: usize, end: usize) -> (Vec<TextSpan>, usize) {
+    fn split_str(
+        content: &str,
+        offset: usize,
+        start: usize,
+        end: usize,
+    ) -> (Vec<TextSpan<'_>>, usize) {
         let (prefix, remainder) = if start == offset {
             (None, content)
         } else {
@@ -163,6 +231,25 @@ impl<'a> TextSpan<'a> {
     }
 }
 
+/// widens `start..=end` to whole chars, so an offset pointing into a surrogate pair
+/// never splits it. `offset` is the utf16 position `content` starts at.
+fn snap_to_chars(content: &str, offset: usize, start: usize, end: usize) -> (usize, usize) {
+    let (mut start_snapped, mut end_snapped) = (start, end);
+    let mut position = offset;
+    for chr in content.chars() {
+        let next = position + chr.len_utf16();
+        if (position..next).contains(&start) {
+            start_snapped = position;
+        }
+        if (position..next).contains(&end) {
+            end_snapped = next - 1;
+        }
+        position = next;
+    }
+
+    (start_snapped, end_snapped)
+}
+
 fn split_at_utf16_offset(content: &str, u16_len: usize) -> (&str, &str) {
     let prefix_len = utf16_to_byte_offset(content, u16_len);
 
@@ -193,7 +280,10 @@ fn utf16_to_byte_offset(content: &str, utf16_offset: usize) -> usize {
 
 #[cfg(test)]
 mod test {
-    use crate::text_markup::{split_at_utf16_offset, utf16_to_byte_offset, SpanContent, TextSpan};
+    use crate::content::Content;
+    use crate::text_markup::{
+        split_at_utf16_offset, utf16_to_byte_offset, SpanContent, SpanWrap, TextSpan,
+    };
 
     #[test]
     fn utf16_index_one_byte_chars() {
@@ -227,6 +317,85 @@ mod test {
         assert_eq!(("L 👋🏽", " R"), split_at_utf16_offset(input, 6));
     }
 
+    #[test]
+    fn test_wraps_nest_in_canonical_order() {
+        let mut span = TextSpan::create("code");
+        span.add_wrap(SpanWrap::Code);
+        span.add_wrap(SpanWrap::Link {
+            href: String::from("https://medium.com/@jdoe"),
+        });
+        span.add_wrap(SpanWrap::Strong);
+
+        let content: Vec<Content> = span.into();
+        let html: Vec<String> = content.iter().map(Content::to_string).collect();
+        assert_eq!(
+            vec![r#"<a href="https://medium.com/@jdoe"><strong><code>code</code></strong></a>"#],
+            html
+        );
+    }
+
+    #[test]
+    fn test_overlapping_wraps_split_the_later_one() {
+        let mut span = TextSpan::create("This is a test with some text");
+        span.wrap_range(10, 16, &SpanWrap::Emphasized);
+        span.wrap_range(8, 13, &SpanWrap::Strong);
+
+        let content: Vec<Content> = span.into();
+        let html: String = content.iter().map(Content::to_string).collect();
+        assert_eq!(
+            "This is <strong>a </strong><em><strong>test</strong> wi</em>th some text",
+            html
+        );
+    }
+
+    #[test]
+    fn test_split_single_char_end() {
+        let mut span = TextSpan::create("0123456789");
+
+        assert_eq!(
+            SpanContent::Text("9"),
+            span.get_sub_span_mut(9, 9).unwrap().content
+        );
+    }
+
+    #[test]
+    fn test_split_trailing_emoji() {
+        let input = "hi 👋🏽";
+
+        let mut span = TextSpan::create(input);
+        assert_eq!(
+            SpanContent::Text("👋🏽"),
+            span.get_sub_span_mut(3, 6).unwrap().content
+        );
+
+        // the skin tone modifier alone, addressed by its low surrogate only
+        let mut span = TextSpan::create(input);
+        assert_eq!(
+            SpanContent::Text("🏽"),
+            span.get_sub_span_mut(6, 6).unwrap().content
+        );
+    }
+
+    #[test]
+    fn test_wrap_of_half_a_surrogate_pair_covers_the_char() {
+        let mut span = TextSpan::create("ab👋");
+        span.wrap_range(3, 3, &SpanWrap::Strong);
+
+        let content: Vec<Content> = span.into();
+        let html: String = content.iter().map(Content::to_string).collect();
+        assert_eq!("ab<strong>👋</strong>", html);
+    }
+
+    #[test]
+    fn test_empty_text_does_not_underflow() {
+        let span = TextSpan::create("");
+        assert_eq!(0, span.end);
+
+        let content: Vec<Content> = span.into();
+        let html: String = content.iter().map(Content::to_string).collect();
+        assert_eq!("", html);
+    }
+
     #[test]
     fn test_does_not_split_for_full_range() {
         let input = "0123456789";
@@ -262,7 +431,7 @@ mod test {
         let input = String::from("0123456789");
 
         let mut span = TextSpan::create(&input);
-        span.get_sub_span_mut(0, 3);
+        span.get_sub_span_mut(0, 3).unwrap();
 
         assert_eq!(
             TextSpan {
@@ -293,7 +462,7 @@ mod test {
         let input = String::from("0123456789");
 
         let mut span = TextSpan::create(&input);
-        span.get_sub_span_mut(6, 9);
+        span.get_sub_span_mut(6, 9).unwrap();
 
         assert_eq!(
             TextSpan {
@@ -324,7 +493,7 @@ mod test {
         let input = String::from("0123456789");
 
         let mut span = TextSpan::create(&input);
-        span.get_sub_span_mut(4, 6);
+        span.get_sub_span_mut(4, 6).unwrap();
 
         assert_eq!(
             TextSpan {
@@ -361,7 +530,7 @@ mod test {
         let input = String::from("0123456789");
 
         let mut span = TextSpan::create(&input);
-        span.get_sub_span_mut(5, 5);
+        span.get_sub_span_mut(5, 5).unwrap();
 
         assert_eq!(
             TextSpan {
@@ -398,7 +567,7 @@ mod test {
         let input = String::from("0123456789");
 
         let mut span = TextSpan::create(&input);
-        span.get_sub_span_mut(3, 7);
+        span.get_sub_span_mut(3, 7).unwrap();
 
         assert_eq!(
             TextSpan {
@@ -429,7 +598,7 @@ mod test {
             span
         );
 
-        span.get_sub_span_mut(5, 6);
+        span.get_sub_span_mut(5, 6).unwrap();
 
         assert_eq!(
             TextSpan {
@@ -0,0 +1,118 @@
+//! conversion of medium's epoch timestamps (milliseconds since 1970-01-01 UTC)
+
+const MONTHS: [&str; 12] = [
+    "January",
+    "February",
+    "March",
+    "April",
+    "May",
+    "June",
+    "July",
+    "August",
+    "September",
+    "October",
+    "November",
+    "December",
+];
+
+/// a point in time in utc, with second precision
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct DateTime {
+    pub year: i64,
+    pub month: u32,
+    pub day: u32,
+    pub hour: u32,
+    pub minute: u32,
+    pub second: u32,
+}
+
+impl DateTime {
+    pub fn from_epoch_ms(ms: i64) -> DateTime {
+        // medium never sends dates before the epoch, anything negative is garbage
+        let secs = ms.max(0) / 1000;
+        let (days, secs_of_day) = (secs / 86_400, secs % 86_400);
+        let (year, month, day) = civil_from_days(days);
+
+        DateTime {
+            year,
+            month,
+            day,
+            hour: (secs_of_day / 3600) as u32,
+            minute: (secs_of_day % 3600 / 60) as u32,
+            second: (secs_of_day % 60) as u32,
+        }
+    }
+
+    /// e.g. `2020-05-15T06:01:09Z`
+    pub fn to_rfc3339(self) -> String {
+        format!(
+            "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+            self.year, self.month, self.day, self.hour, self.minute, self.second
+        )
+    }
+
+    /// the date only, e.g. `May 15, 2020`
+    pub fn to_human(self) -> String {
+        format!(
+            "{} {}, {}",
+            MONTHS[self.month as usize - 1],
+            self.day,
+            self.year
+        )
+    }
+}
+
+/// converts days since the epoch into a (year, month, day) triple,
+/// see http://howardhinnant.github.io/date_algorithms.html#civil_from_days
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = yoe + era * 400 + i64::from(month <= 2);
+
+    (year, month, day)
+}
+
+/// formats a millisecond epoch as an rfc3339 utc timestamp, e.g. `2020-05-15T06:01:09Z`
+pub fn epoch_to_rfc3339(ms: i64) -> String {
+    DateTime::from_epoch_ms(ms).to_rfc3339()
+}
+
+/// formats a millisecond epoch as a human readable date, e.g. `May 15, 2020`
+pub fn epoch_to_human(ms: i64) -> String {
+    DateTime::from_epoch_ms(ms).to_human()
+}
+
+#[cfg(test)]
+mod test {
+    use crate::timestamp::{epoch_to_human, epoch_to_rfc3339};
+
+    #[test]
+    fn test_epoch_start() {
+        assert_eq!("1970-01-01T00:00:00Z", epoch_to_rfc3339(0));
+        assert_eq!("January 1, 1970", epoch_to_human(0));
+    }
+
+    #[test]
+    fn test_known_epoch() {
+        assert_eq!("2020-05-15T06:01:09Z", epoch_to_rfc3339(1589522469693));
+        assert_eq!("May 15, 2020", epoch_to_human(1589522469693));
+    }
+
+    #[test]
+    fn test_leap_day() {
+        assert_eq!("2024-02-29T23:59:59Z", epoch_to_rfc3339(1709251199000));
+        assert_eq!("February 29, 2024", epoch_to_human(1709251199000));
+    }
+
+    #[test]
+    fn test_pre_epoch_is_clamped() {
+        assert_eq!("1970-01-01T00:00:00Z", epoch_to_rfc3339(-86_400_000));
+        assert_eq!("January 1, 1970", epoch_to_human(-1));
+    }
+}